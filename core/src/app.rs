@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::limits::ResourceLimits;
+use crate::security::SecurityOptions;
+
+// Per-app settings read from <app>.yaml, see app_config_paths
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AppDefinition {
+    // Command to run instead of Firefox, e.g. for apps started with
+    // `--backend native`; {profile} is replaced by the profile directory
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    #[serde(default)]
+    pub security: SecurityOptions,
+}
+
+impl AppDefinition {
+    // Apps without a definition file run with Docker's default limits and
+    // the hardened security settings. `explicit` is an --app-config path,
+    // which must exist; otherwise the first of app_config_paths found is read.
+    pub fn load(app: &str, explicit: Option<&Path>) -> Result<Self, String> {
+        let path = match explicit {
+            Some(path) if !path.exists() => return Err(format!("App config {} does not exist", path.display())),
+            Some(path) => path.to_path_buf(),
+            None => match app_config_paths(app).into_iter().find(|path| path.exists()) {
+                Some(path) => path,
+                None => return Ok(AppDefinition::default()),
+            },
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_yaml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+}
+
+// Where an app's definition may be, first match wins: the project's
+// configs/apps, the user's and the system's menu_runner/apps. The project
+// path is relative, so it only counts when started from the repository.
+pub fn app_config_paths(app: &str) -> Vec<PathBuf> {
+    let file = format!("{}.yaml", app);
+    let mut paths = vec![Path::new("configs/apps").join(&file)];
+
+    let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = user_dir {
+        paths.push(dir.join("menu_runner/apps").join(&file));
+    }
+
+    paths.push(Path::new("/etc/menu_runner/apps").join(&file));
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_project_directory_comes_first_and_the_system_one_last() {
+        let paths = app_config_paths("firefox");
+        assert_eq!(paths[0], Path::new("configs/apps/firefox.yaml"));
+        assert_eq!(paths[paths.len() - 1], Path::new("/etc/menu_runner/apps/firefox.yaml"));
+    }
+
+    #[test]
+    fn an_explicit_app_config_is_read_and_must_exist() {
+        let path = std::env::temp_dir().join(format!("menu-runner-app-{}.yaml", std::process::id()));
+        std::fs::write(&path, "command: [sleep, infinity]\n").unwrap();
+        let loaded = AppDefinition::load("firefox", Some(&path));
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(loaded.unwrap().command, ["sleep", "infinity"]);
+        assert!(AppDefinition::load("firefox", Some(&path)).unwrap_err().contains("does not exist"));
+    }
+}
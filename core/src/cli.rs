@@ -0,0 +1,30 @@
+// Helpers for the option flags that follow `<function> <app> <profile>`
+
+// Value following `name` in the trailing option flags, e.g. `--grace 30`
+pub fn flag_value<'a>(flags: &'a [String], name: &str) -> Option<&'a str> {
+    flags
+        .iter()
+        .position(|flag| flag == name)
+        .and_then(|index| flags.get(index + 1))
+        .map(|value| value.as_str())
+}
+
+// Every value given for a flag that may be repeated, e.g. `--device-read-bps`
+pub fn flag_values(flags: &[String], name: &str) -> Vec<String> {
+    flags
+        .windows(2)
+        .filter(|pair| pair[0] == name)
+        .map(|pair| pair[1].clone())
+        .collect()
+}
+
+// Parse a numeric flag, reporting which flag was malformed
+pub fn parse_flag<T: std::str::FromStr>(flags: &[String], name: &str) -> Result<Option<T>, String> {
+    match flag_value(flags, name) {
+        Some(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| format!("Invalid {} value: {}", name, value)),
+        None => Ok(None),
+    }
+}
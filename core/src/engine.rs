@@ -0,0 +1,676 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Map, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use crate::limits::parse_bytes;
+use crate::network::NetworkPolicy;
+use crate::spec::ContainerSpec;
+
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+// Client for the Docker Engine API (or Podman's Docker-compatible API) spoken
+// as plain HTTP/1.1 over the daemon's Unix socket
+#[derive(Debug, Clone)]
+pub struct DockerApi {
+    socket: PathBuf,
+}
+
+// A response with its status code and body; the body of a failed request
+// carries the daemon's error message
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl DockerApi {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        DockerApi { socket: socket.into() }
+    }
+
+    // Pick the socket from DOCKER_HOST, the standard Docker location or the
+    // rootless Podman socket, in that order
+    pub fn detect() -> Self {
+        if let Some(path) = std::env::var("DOCKER_HOST")
+            .ok()
+            .and_then(|host| host.strip_prefix("unix://").map(str::to_string))
+        {
+            return DockerApi::new(path);
+        }
+
+        if !Path::new(DOCKER_SOCKET).exists() {
+            if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+                let podman = Path::new(&runtime_dir).join("podman/podman.sock");
+                if podman.exists() {
+                    return DockerApi::new(podman);
+                }
+            }
+        }
+
+        DockerApi::new(DOCKER_SOCKET)
+    }
+
+    pub async fn create_container(&self, spec: &ContainerSpec) -> Result<String, String> {
+        let body = create_body(spec)?;
+        let path = format!("/containers/create?name={}", encode_query(&spec.name));
+        let response = self.call("POST", &path, Some(&body)).await?;
+        let created: Value = parse_json(&response.body)?;
+
+        Ok(created["Id"].as_str().unwrap_or_default().to_string())
+    }
+
+    pub async fn start_container(&self, name: &str) -> Result<(), String> {
+        self.call("POST", &container_path(name, "/start"), None).await?;
+        Ok(())
+    }
+
+    pub async fn pause_container(&self, name: &str) -> Result<(), String> {
+        self.call("POST", &container_path(name, "/pause"), None).await?;
+        Ok(())
+    }
+
+    pub async fn unpause_container(&self, name: &str) -> Result<(), String> {
+        self.call("POST", &container_path(name, "/unpause"), None).await?;
+        Ok(())
+    }
+
+    pub async fn kill_container(&self, name: &str, signal: &str) -> Result<(), String> {
+        let path = format!("{}?signal={}", container_path(name, "/kill"), encode_query(signal));
+        self.call("POST", &path, None).await?;
+        Ok(())
+    }
+
+    pub async fn remove_container(&self, name: &str) -> Result<(), String> {
+        self.call("DELETE", &container_path(name, ""), None).await?;
+        Ok(())
+    }
+
+    // Full `docker inspect` document, or None when the container does not exist
+    pub async fn inspect_container(&self, name: &str) -> Result<Option<Value>, String> {
+        let response = self.send("GET", &container_path(name, "/json"), None).await?;
+
+        match response.status {
+            404 => Ok(None),
+            200..=299 => parse_json(&response.body).map(Some),
+            _ => Err(error_message(&response)),
+        }
+    }
+
+    // Open the daemon's event stream, limited to container events
+    pub async fn events(&self) -> Result<EventStream, String> {
+        let filters = json!({ "type": ["container"] }).to_string();
+        let path = format!("/events?filters={}", encode_query(&filters));
+        let mut stream = self.connect().await?;
+        write_request(&mut stream, "GET", &path, None).await?;
+
+        let mut reader = BufReader::new(stream);
+        let head = read_head(&mut reader).await?;
+        if !(200..300).contains(&head.status) {
+            let body = read_body(&mut reader, &head).await?;
+            return Err(error_message(&Response { status: head.status, body }));
+        }
+
+        Ok(EventStream { reader, chunked: head.chunked, pending: Vec::new() })
+    }
+
+    async fn connect(&self) -> Result<UnixStream, String> {
+        UnixStream::connect(&self.socket)
+            .await
+            .map_err(|e| format!("Failed to connect to {}: {}", self.socket.display(), e))
+    }
+
+    // Send a request and turn any non-2xx status into the daemon's error message
+    async fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Response, String> {
+        let response = self.send(method, path, body).await?;
+
+        if (200..300).contains(&response.status) {
+            Ok(response)
+        } else {
+            Err(error_message(&response))
+        }
+    }
+
+    async fn send(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Response, String> {
+        let mut stream = self.connect().await?;
+        write_request(&mut stream, method, path, body).await?;
+
+        let mut reader = BufReader::new(stream);
+        let head = read_head(&mut reader).await?;
+        let body = read_body(&mut reader, &head).await?;
+
+        Ok(Response { status: head.status, body })
+    }
+}
+
+// A container lifecycle event as reported by the daemon
+#[derive(Debug, Clone)]
+pub struct EngineEvent {
+    pub action: String,
+    pub container: String,
+}
+
+// Newline-delimited JSON events read from a long-lived /events response
+pub struct EventStream {
+    reader: BufReader<UnixStream>,
+    chunked: bool,
+    pending: Vec<u8>,
+}
+
+impl EventStream {
+    // The next container event, or None once the daemon closes the stream
+    pub async fn next_event(&mut self) -> Result<Option<EngineEvent>, String> {
+        loop {
+            if let Some(newline) = self.pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.pending.drain(..=newline).collect();
+                if let Some(event) = parse_event(&line) {
+                    return Ok(Some(event));
+                }
+                continue;
+            }
+
+            let data = if self.chunked {
+                read_chunk(&mut self.reader).await?
+            } else {
+                let mut buffer = vec![0; 4096];
+                let read = self.reader.read(&mut buffer).await.map_err(|e| e.to_string())?;
+                buffer.truncate(read);
+                Some(buffer)
+            };
+
+            match data {
+                Some(data) if !data.is_empty() => self.pending.extend(data),
+                _ => return Ok(None),
+            }
+        }
+    }
+}
+
+fn parse_event(line: &[u8]) -> Option<EngineEvent> {
+    let value: Value = serde_json::from_slice(line).ok()?;
+    // Newer daemons report e.g. "exec_start: sh" for exec events; keep the verb
+    let action = value["Action"].as_str()?.split(':').next()?.trim().to_string();
+    let container = value["Actor"]["Attributes"]["name"].as_str()?.to_string();
+
+    Some(EngineEvent { action, container })
+}
+
+// Request body for POST /containers/create
+fn create_body(spec: &ContainerSpec) -> Result<Value, String> {
+    let limits = &spec.limits;
+    let mut host_config = json!({
+        "AutoRemove": spec.auto_remove,
+        "Binds": spec.binds,
+    });
+
+    if let Some(cpus) = &limits.cpus {
+        let cpus: f64 = cpus.parse().map_err(|_| format!("Invalid cpus value: {}", cpus))?;
+        host_config["NanoCpus"] = json!((cpus * 1e9) as i64);
+    }
+    if let Some(memory) = &limits.memory {
+        host_config["Memory"] = json!(parse_bytes(memory)?);
+    }
+    if let Some(pids_limit) = limits.pids_limit {
+        host_config["PidsLimit"] = json!(pids_limit);
+    }
+    if let Some(blkio_weight) = limits.blkio_weight {
+        host_config["BlkioWeight"] = json!(blkio_weight);
+    }
+    if !limits.device_read_bps.is_empty() {
+        host_config["BlkioDeviceReadBps"] = device_rates(&limits.device_read_bps)?;
+    }
+    if !limits.device_write_bps.is_empty() {
+        host_config["BlkioDeviceWriteBps"] = device_rates(&limits.device_write_bps)?;
+    }
+
+    match &spec.network {
+        NetworkPolicy::Default => {}
+        NetworkPolicy::None => host_config["NetworkMode"] = json!("none"),
+        NetworkPolicy::Host => host_config["NetworkMode"] = json!("host"),
+        NetworkPolicy::Named(name) => host_config["NetworkMode"] = json!(name),
+        NetworkPolicy::Proxy(container) => host_config["NetworkMode"] = json!(format!("container:{}", container)),
+    }
+
+    let security = &spec.security;
+    if !security.cap_drop.is_empty() {
+        host_config["CapDrop"] = json!(security.cap_drop);
+    }
+    if !security.cap_add.is_empty() {
+        host_config["CapAdd"] = json!(security.cap_add);
+    }
+    if security.read_only {
+        host_config["ReadonlyRootfs"] = json!(true);
+    }
+    if !security.tmpfs.is_empty() {
+        let mounts: Map<String, Value> = security
+            .tmpfs
+            .iter()
+            .map(|mount| {
+                let (path, options) = mount.split_once(':').unwrap_or((mount, ""));
+                (path.to_string(), json!(options))
+            })
+            .collect();
+        host_config["Tmpfs"] = Value::Object(mounts);
+    }
+
+    // The API takes the seccomp profile itself rather than a path to it
+    let mut security_opt = Vec::new();
+    if let Some(seccomp) = &security.seccomp {
+        let profile = std::fs::read_to_string(seccomp)
+            .map_err(|e| format!("Failed to read seccomp profile {}: {}", seccomp, e))?;
+        security_opt.push(format!("seccomp={}", profile));
+    }
+    if security.no_new_privileges {
+        security_opt.push("no-new-privileges".to_string());
+    }
+    if !security_opt.is_empty() {
+        host_config["SecurityOpt"] = json!(security_opt);
+    }
+
+    Ok(json!({
+        "Image": spec.image,
+        "Cmd": spec.command,
+        "Env": spec.env,
+        "User": spec.user,
+        "HostConfig": host_config,
+    }))
+}
+
+// device:rate pairs, e.g. /dev/sda:10mb
+fn device_rates(rates: &[String]) -> Result<Value, String> {
+    rates
+        .iter()
+        .map(|rate| {
+            let (path, bytes) = rate
+                .rsplit_once(':')
+                .ok_or_else(|| format!("Invalid device rate: {}", rate))?;
+            Ok(json!({ "Path": path, "Rate": parse_bytes(bytes)? }))
+        })
+        .collect::<Result<Vec<Value>, String>>()
+        .map(Value::Array)
+}
+
+struct ResponseHead {
+    status: u16,
+    chunked: bool,
+    content_length: Option<usize>,
+}
+
+async fn write_request(stream: &mut UnixStream, method: &str, path: &str, body: Option<&Value>) -> Result<(), String> {
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: docker\r\nConnection: close\r\n", method, path);
+
+    if !body.is_empty() {
+        request.push_str("Content-Type: application/json\r\n");
+    }
+    request.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    request.push_str(&body);
+
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))
+}
+
+async fn read_head<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Result<ResponseHead, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.map_err(|e| e.to_string())?;
+
+    // HTTP/1.1 200 OK
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| format!("Malformed response from daemon: {}", line.trim()))?;
+
+    let mut head = ResponseHead { status, chunked: false, content_length: None };
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).await.map_err(|e| e.to_string())?;
+        let header = line.trim_end();
+        if read == 0 || header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "transfer-encoding" => head.chunked = value.eq_ignore_ascii_case("chunked"),
+                "content-length" => head.content_length = value.parse().ok(),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(head)
+}
+
+async fn read_body<R: AsyncRead + Unpin>(reader: &mut BufReader<R>, head: &ResponseHead) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+
+    if head.chunked {
+        while let Some(chunk) = read_chunk(reader).await? {
+            body.extend(chunk);
+        }
+    } else if let Some(length) = head.content_length {
+        body.resize(length, 0);
+        reader.read_exact(&mut body).await.map_err(|e| e.to_string())?;
+    } else {
+        reader.read_to_end(&mut body).await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(body)
+}
+
+// One chunk of a chunked transfer encoding, or None for the terminating chunk
+async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Result<Option<Vec<u8>>, String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await.map_err(|e| e.to_string())? == 0 {
+        return Ok(None);
+    }
+
+    let size_field = line.trim().split(';').next().unwrap_or_default();
+    let size = usize::from_str_radix(size_field, 16)
+        .map_err(|_| format!("Malformed chunk size: {}", line.trim()))?;
+    if size == 0 {
+        return Ok(None);
+    }
+
+    let mut chunk = vec![0; size + 2];
+    reader.read_exact(&mut chunk).await.map_err(|e| e.to_string())?;
+    chunk.truncate(size);
+
+    Ok(Some(chunk))
+}
+
+fn parse_json(body: &[u8]) -> Result<Value, String> {
+    serde_json::from_slice(body).map_err(|e| format!("Malformed response from daemon: {}", e))
+}
+
+// Failed requests carry {"message": "..."} with the daemon's explanation
+fn error_message(response: &Response) -> String {
+    serde_json::from_slice::<Value>(&response.body)
+        .ok()
+        .and_then(|value| value["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("Daemon returned HTTP {}", response.status))
+}
+
+// The name is a path segment of its own, whatever characters it holds
+fn container_path(name: &str, endpoint: &str) -> String {
+    format!("/containers/{}{}", encode_query(name), endpoint)
+}
+
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::net::UnixListener;
+
+    use crate::limits::ResourceLimits;
+    use crate::security::SecurityOptions;
+
+    // The request line and body of each request the fake daemon answered
+    type Requests = Arc<Mutex<Vec<(String, String)>>>;
+
+    // The fake daemon's socket file, removed when the test is done
+    struct Socket(PathBuf);
+
+    impl Drop for Socket {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    // A socket of its own for each fake daemon
+    fn listen() -> (PathBuf, UnixListener) {
+        static SOCKETS: AtomicUsize = AtomicUsize::new(0);
+        let socket = std::env::temp_dir().join(format!(
+            "menu-runner-engine-{}-{}.sock",
+            std::process::id(),
+            SOCKETS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        (socket, listener)
+    }
+
+    // A daemon on its own socket answering each request with what `respond`
+    // returns for its request line
+    fn fake_daemon(respond: fn(&str) -> String) -> (DockerApi, Requests, Socket) {
+        let (socket, listener) = listen();
+
+        let requests = Requests::default();
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut reader = BufReader::new(stream);
+                let (request_line, body) = read_request(&mut reader).await;
+
+                let response = respond(&request_line);
+                seen.lock().unwrap().push((request_line, body));
+                reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (DockerApi::new(&socket), requests, Socket(socket))
+    }
+
+    // A daemon answering one request by writing `writes` one after the other,
+    // with a pause in between so each arrives in a read of its own, and then
+    // closing the connection
+    fn streaming_daemon(writes: Vec<String>) -> (DockerApi, Socket) {
+        let (socket, listener) = listen();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            read_request(&mut reader).await;
+
+            let mut stream = reader.into_inner();
+            for write in writes {
+                stream.write_all(write.as_bytes()).await.unwrap();
+                stream.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        (DockerApi::new(&socket), Socket(socket))
+    }
+
+    // The request line, without the HTTP version, and the body of a request
+    async fn read_request(reader: &mut BufReader<UnixStream>) -> (String, String) {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await.unwrap();
+        let request_line = request_line.trim_end().trim_end_matches(" HTTP/1.1").to_string();
+
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).await.unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.unwrap();
+        (request_line, String::from_utf8(body).unwrap())
+    }
+
+    fn reply(status: &str, body: &str) -> String {
+        format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body)
+    }
+
+    fn request_lines(requests: &Requests) -> Vec<String> {
+        requests.lock().unwrap().iter().map(|(line, _)| line.clone()).collect()
+    }
+
+    fn spec(name: &str) -> ContainerSpec {
+        ContainerSpec {
+            name: name.to_string(),
+            image: "firefox".to_string(),
+            command: Vec::new(),
+            env: vec!["DISPLAY=:0".to_string()],
+            binds: Vec::new(),
+            user: "firefoxuser".to_string(),
+            auto_remove: true,
+            limits: ResourceLimits::default(),
+            network: NetworkPolicy::None,
+            security: SecurityOptions::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn create_sends_the_spec_and_returns_the_id() {
+        let (api, requests, _socket) = fake_daemon(|_| reply("201 Created", r#"{"Id":"abc123","Warnings":[]}"#));
+
+        let id = api.create_container(&spec("firefox_work")).await.unwrap();
+
+        assert_eq!(id, "abc123");
+        let (line, body) = requests.lock().unwrap()[0].clone();
+        assert_eq!(line, "POST /containers/create?name=firefox_work");
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["Image"], "firefox");
+        assert_eq!(body["HostConfig"]["AutoRemove"], true);
+        assert_eq!(body["HostConfig"]["NetworkMode"], "none");
+    }
+
+    #[tokio::test]
+    async fn lifecycle_calls_use_the_container_path() {
+        let (api, requests, _socket) = fake_daemon(|_| "HTTP/1.1 204 No Content\r\n\r\n".to_string());
+
+        api.start_container("firefox_work").await.unwrap();
+        api.pause_container("firefox_work").await.unwrap();
+        api.unpause_container("firefox_work").await.unwrap();
+        api.kill_container("firefox_work", "SIGTERM").await.unwrap();
+        api.remove_container("firefox_work").await.unwrap();
+
+        assert_eq!(
+            request_lines(&requests),
+            [
+                "POST /containers/firefox_work/start",
+                "POST /containers/firefox_work/pause",
+                "POST /containers/firefox_work/unpause",
+                "POST /containers/firefox_work/kill?signal=SIGTERM",
+                "DELETE /containers/firefox_work",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn container_names_are_encoded() {
+        let (api, requests, _socket) = fake_daemon(|_| "HTTP/1.1 204 No Content\r\n\r\n".to_string());
+
+        api.start_container("../images/x?y").await.unwrap();
+
+        assert_eq!(request_lines(&requests), ["POST /containers/..%2Fimages%2Fx%3Fy/start"]);
+    }
+
+    #[tokio::test]
+    async fn inspect_returns_the_document() {
+        let (api, _, _socket) = fake_daemon(|_| reply("200 OK", r#"{"State":{"Status":"paused","Pid":42}}"#));
+
+        let info = api.inspect_container("firefox_work").await.unwrap().unwrap();
+
+        assert_eq!(info["State"]["Status"], "paused");
+    }
+
+    #[tokio::test]
+    async fn not_found_is_none_for_inspect_and_an_error_otherwise() {
+        let (api, _, _socket) = fake_daemon(|_| reply("404 Not Found", r#"{"message":"No such container: firefox_work"}"#));
+
+        assert_eq!(api.inspect_container("firefox_work").await.unwrap(), None);
+        assert_eq!(
+            api.start_container("firefox_work").await.unwrap_err(),
+            "No such container: firefox_work"
+        );
+    }
+
+    #[tokio::test]
+    async fn chunked_responses_are_joined() {
+        let (api, _, _socket) = fake_daemon(|_| {
+            let body = r#"{"State":{"Status":"running"}}"#;
+            let (first, second) = body.split_at(10);
+            format!(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                first.len(),
+                first,
+                second.len(),
+                second
+            )
+        });
+
+        let info = api.inspect_container("firefox_work").await.unwrap().unwrap();
+
+        assert_eq!(info["State"]["Status"], "running");
+    }
+
+    #[tokio::test]
+    async fn streamed_events_are_decoded_across_chunks_and_writes() {
+        let first = r#"{"Action":"pause","Actor":{"Attributes":{"name":"firefox_work"}}}"#.to_string() + "\n";
+        let second = r#"{"Action":"die","Actor":{"Attributes":{"name":"firefox_mail"}}}"#.to_string() + "\n";
+        let chunk = |data: &str| format!("{:x}\r\n{}\r\n", data.len(), data);
+        // The second event's JSON is split over two chunks, and the chunk
+        // framing itself over two writes
+        let (second_start, second_end) = second.split_at(20);
+        let writes = vec![
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_string(),
+            chunk(&first),
+            chunk(second_start),
+            chunk(second_end)[..5].to_string(),
+            chunk(second_end)[5..].to_string() + "0\r\n\r\n",
+        ];
+        let (api, _socket) = streaming_daemon(writes);
+
+        let mut events = api.events().await.unwrap();
+
+        let event = events.next_event().await.unwrap().unwrap();
+        assert_eq!((event.action.as_str(), event.container.as_str()), ("pause", "firefox_work"));
+        let event = events.next_event().await.unwrap().unwrap();
+        assert_eq!((event.action.as_str(), event.container.as_str()), ("die", "firefox_mail"));
+        // The terminating chunk ends the stream
+        assert!(events.next_event().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn a_closed_plain_stream_ends_after_its_last_event() {
+        let event = r#"{"Action":"start","Actor":{"Attributes":{"name":"firefox_work"}}}"#;
+        let (head, tail) = event.split_at(30);
+        let writes = vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n".to_string(),
+            head.to_string(),
+            tail.to_string() + "\n",
+        ];
+        let (api, _socket) = streaming_daemon(writes);
+
+        let mut events = api.events().await.unwrap();
+
+        let event = events.next_event().await.unwrap().unwrap();
+        assert_eq!((event.action.as_str(), event.container.as_str()), ("start", "firefox_work"));
+        assert!(events.next_event().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn events_keep_the_verb_and_container_name() {
+        let line = br#"{"Action":"exec_start: sh","Actor":{"Attributes":{"name":"firefox_work"}}}"#;
+        let event = parse_event(line).unwrap();
+
+        assert_eq!(event.action, "exec_start");
+        assert_eq!(event.container, "firefox_work");
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt;
+
+// Error type for container lifecycle operations
+#[derive(Debug)]
+pub enum SystemError {
+    // The docker CLI or the daemon reported a failure
+    Runtime(String),
+    // An app definition or snapshot/profile file could not be read or written
+    Config(String),
+    // A malformed flag or argument
+    InvalidArgument(String),
+    // The container is in a state that does not allow the operation
+    InvalidState(String),
+}
+
+impl fmt::Display for SystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemError::Runtime(msg) => write!(f, "{}", msg),
+            SystemError::Config(msg) => write!(f, "{}", msg),
+            SystemError::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            SystemError::InvalidState(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SystemError {}
@@ -0,0 +1,23 @@
+// core/src/lib.rs
+// Container lifecycle operations for Menu_Runner profiles, shared by the
+// Menu_Runner_system binary and the menu, which calls them in-process
+mod app;
+mod cli;
+pub mod engine;
+pub mod error;
+pub mod lifecycle;
+pub mod limits;
+pub mod native;
+pub mod network;
+pub mod profile;
+pub mod runtime;
+pub mod security;
+pub mod sharing;
+pub mod snapshot;
+pub mod spec;
+
+pub use engine::EngineEvent;
+pub use error::SystemError;
+pub use lifecycle::{freeze, kill, remove, run_action, start, status, stop, unfreeze};
+pub use lifecycle::{ContainerStatus, StartOptions, StopOutcome, Target};
+pub use runtime::Runtime;
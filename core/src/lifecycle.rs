@@ -0,0 +1,544 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+
+use crate::app::AppDefinition;
+use crate::cli::{flag_value, parse_flag};
+use crate::error::SystemError;
+use crate::limits::ResourceLimits;
+use crate::network::NetworkPolicy;
+use crate::runtime::Runtime;
+use crate::security::{self, MachineId, SecurityOptions};
+use crate::sharing::{ClipboardPolicy, Downloads, CONTAINER_DOWNLOADS_PATH};
+use crate::snapshot::{self, RetentionPolicy};
+use crate::profile;
+use crate::spec::ContainerSpec;
+
+// Snapshots kept per profile when no --keep is given
+const DEFAULT_SNAPSHOT_KEEP: usize = 10;
+
+// Seconds a container gets to exit after SIGTERM before `stop` escalates to a kill
+const DEFAULT_STOP_GRACE_SECS: u64 = 10;
+
+// Where the profile directory is mounted inside the Firefox container
+const CONTAINER_PROFILE_PATH: &str = "/home/firefoxuser/profile";
+
+// An app profile and the container it runs in
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub app: String,
+    pub profile: String,
+    pub container_name: String,
+}
+
+impl Target {
+    // Containers are named app.<app>-<profile directory name>
+    pub fn new(app: &str, profile: &str) -> Result<Self, SystemError> {
+        let profile = profile.trim_end_matches('/').to_string();
+        let profile_part = Path::new(&profile)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| SystemError::InvalidArgument(format!("Invalid profile path: {}", profile)))?
+            .to_string();
+
+        Ok(Target {
+            app: app.to_string(),
+            container_name: format!("app.{}-{}", app, profile_part),
+            profile,
+        })
+    }
+}
+
+// Container state as reported by the runtime
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContainerStatus {
+    NotFound,
+    Running,
+    Paused,
+    Exited,
+    Other(String),
+}
+
+impl ContainerStatus {
+    fn from_state(state: Option<String>) -> Self {
+        match state.as_deref() {
+            None => ContainerStatus::NotFound,
+            Some("running") => ContainerStatus::Running,
+            Some("paused") => ContainerStatus::Paused,
+            Some("exited") => ContainerStatus::Exited,
+            Some(other) => ContainerStatus::Other(other.to_string()),
+        }
+    }
+
+    // Whether the container still holds its processes (frozen ones included)
+    pub fn is_alive(&self) -> bool {
+        match self {
+            ContainerStatus::Running | ContainerStatus::Paused => true,
+            ContainerStatus::Other(state) => state == "restarting",
+            ContainerStatus::NotFound | ContainerStatus::Exited => false,
+        }
+    }
+}
+
+impl fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerStatus::NotFound => write!(f, "not running"),
+            ContainerStatus::Running => write!(f, "running"),
+            ContainerStatus::Paused => write!(f, "paused"),
+            ContainerStatus::Exited => write!(f, "exited"),
+            ContainerStatus::Other(state) => write!(f, "{}", state),
+        }
+    }
+}
+
+// How a graceful stop ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopOutcome {
+    Exited,
+    Killed,
+}
+
+// Settings for launching a profile container
+#[derive(Debug, Clone)]
+pub struct StartOptions {
+    pub display: String,
+    pub command: Vec<String>,
+    pub limits: ResourceLimits,
+    pub network: NetworkPolicy,
+    pub security: SecurityOptions,
+    pub downloads: Option<Downloads>,
+    // Left alone when unset, so prefs the user changed in the profile stay
+    pub clipboard: Option<ClipboardPolicy>,
+}
+
+impl StartOptions {
+    // Combine the app definition with any limits/network flags, which win
+    pub fn from_flags(app: &str, flags: &[String]) -> Result<Self, SystemError> {
+        let app_config = flag_value(flags, "--app-config").map(Path::new);
+        let definition = AppDefinition::load(app, app_config).map_err(SystemError::Config)?;
+        let limits = ResourceLimits::from_flags(flags)
+            .map_err(SystemError::InvalidArgument)?
+            .merged_over(&definition.limits);
+        let network = match flag_value(flags, "--network") {
+            Some(value) => NetworkPolicy::parse(value).map_err(SystemError::InvalidArgument)?,
+            None => NetworkPolicy::Default,
+        };
+        let clipboard = flag_value(flags, "--clipboard")
+            .map(ClipboardPolicy::parse)
+            .transpose()
+            .map_err(SystemError::InvalidArgument)?;
+        let display = std::env::var("DISPLAY")
+            .map_err(|_| SystemError::Config("DISPLAY is not set".to_string()))?;
+
+        Ok(StartOptions {
+            display,
+            command: definition.command,
+            limits,
+            network,
+            security: definition.security,
+            downloads: Downloads::from_flags(flags),
+            clipboard,
+        })
+    }
+}
+
+// The Firefox container every profile runs in: X11 and PulseAudio forwarded
+// from the host, the profile directory bind-mounted, running as the host user.
+// Apps with their own command in the app definition run that instead.
+fn firefox_spec(target: &Target, options: &StartOptions) -> Result<ContainerSpec, SystemError> {
+    let user_id = users::get_current_uid();
+
+    let mut binds = vec![
+        "/tmp/.X11-unix:/tmp/.X11-unix:z".to_string(),
+        format!("/run/user/{}/pulse:/run/user/{}/pulse", user_id, user_id),
+        format!("{}:{}", target.profile, CONTAINER_PROFILE_PATH),
+    ];
+    match options.security.machine_id {
+        MachineId::Host => binds.push("/etc/machine-id:/etc/machine-id:ro".to_string()),
+        MachineId::Synthetic => {
+            let path = security::synthetic_machine_id(&security::default_machine_id_root(), &target.container_name)
+                .map_err(SystemError::Config)?;
+            binds.push(format!("{}:/etc/machine-id:ro", path.display()));
+        }
+        MachineId::None => {}
+    }
+    if let Some(downloads) = &options.downloads {
+        let dir = downloads.host_dir(&target.profile).map_err(SystemError::Config)?;
+        binds.push(format!("{}:{}", dir, CONTAINER_DOWNLOADS_PATH));
+    }
+
+    Ok(ContainerSpec {
+        name: target.container_name.clone(),
+        image: "fedora-x11-test".to_string(),
+        command: if options.command.is_empty() {
+            vec![
+                "firefox".to_string(),
+                "-profile".to_string(),
+                CONTAINER_PROFILE_PATH.to_string(),
+            ]
+        } else {
+            options
+                .command
+                .iter()
+                .map(|arg| arg.replace("{profile}", CONTAINER_PROFILE_PATH))
+                .collect()
+        },
+        env: vec![
+            format!("DISPLAY={}", options.display),
+            format!("PULSE_SERVER=unix:/run/user/{}/pulse/native", user_id),
+        ],
+        binds,
+        user: format!("{}:{}", user_id, users::get_current_gid()),
+        auto_remove: true,
+        limits: options.limits.clone(),
+        network: options.network.clone(),
+        security: options.security.clone(),
+    })
+}
+
+pub async fn start(runtime: &Runtime, target: &Target, options: &StartOptions) -> Result<(), SystemError> {
+    if let Some(clipboard) = &options.clipboard {
+        clipboard.apply(&target.profile).map_err(SystemError::Config)?;
+    }
+
+    runtime
+        .run(&firefox_spec(target, options)?)
+        .await
+        .map_err(|e| SystemError::Runtime(format!("Failed to start container: {}", e)))
+}
+
+pub async fn freeze(runtime: &Runtime, target: &Target) -> Result<(), SystemError> {
+    runtime
+        .pause(&target.container_name)
+        .await
+        .map_err(|e| SystemError::Runtime(format!("Failed to freeze container: {}", e)))
+}
+
+pub async fn unfreeze(runtime: &Runtime, target: &Target) -> Result<(), SystemError> {
+    runtime
+        .unpause(&target.container_name)
+        .await
+        .map_err(|e| SystemError::Runtime(format!("Failed to unfreeze container: {}", e)))
+}
+
+// Ask the container to shut down with SIGTERM so Firefox can flush its profile
+// databases, and only fall back to SIGKILL once the grace period has run out
+pub async fn stop(runtime: &Runtime, target: &Target, grace: Duration) -> Result<StopOutcome, SystemError> {
+    runtime
+        .kill(&target.container_name, "SIGTERM")
+        .await
+        .map_err(|e| SystemError::Runtime(format!("Failed to stop container: {}", e)))?;
+
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if !status(runtime, target).await?.is_alive() {
+            return Ok(StopOutcome::Exited);
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    // A container started with --rm is gone as soon as it exits, which may
+    // have happened since the last poll
+    match kill(runtime, target).await {
+        Ok(()) => Ok(StopOutcome::Killed),
+        Err(e) => match status(runtime, target).await? {
+            state if !state.is_alive() => Ok(StopOutcome::Exited),
+            _ => Err(e),
+        },
+    }
+}
+
+pub async fn kill(runtime: &Runtime, target: &Target) -> Result<(), SystemError> {
+    runtime
+        .kill(&target.container_name, "SIGKILL")
+        .await
+        .map_err(|e| SystemError::Runtime(format!("Failed to kill container: {}", e)))
+}
+
+// Remove a container that exited but was left behind, e.g. after a crash
+pub async fn remove(runtime: &Runtime, target: &Target) -> Result<(), SystemError> {
+    runtime
+        .remove(&target.container_name)
+        .await
+        .map_err(|e| SystemError::Runtime(format!("Failed to remove container: {}", e)))
+}
+
+pub async fn status(runtime: &Runtime, target: &Target) -> Result<ContainerStatus, SystemError> {
+    runtime
+        .state(&target.container_name)
+        .await
+        .map(ContainerStatus::from_state)
+        .map_err(SystemError::Runtime)
+}
+
+// Archiving a profile Firefox is writing to gives a corrupt snapshot, so only
+// allow it when the container is gone or frozen
+pub async fn take_snapshot(
+    runtime: &Runtime,
+    target: &Target,
+    root: &Path,
+    retention: &RetentionPolicy,
+) -> Result<String, SystemError> {
+    match status(runtime, target).await? {
+        ContainerStatus::NotFound | ContainerStatus::Paused | ContainerStatus::Exited => {}
+        state => {
+            return Err(SystemError::InvalidState(format!(
+                "Container {} is {}; freeze or stop it before taking a snapshot, or pass --freeze",
+                target.container_name, state
+            )))
+        }
+    }
+
+    let snapshot = snapshot::create_snapshot(root, &target.container_name, &target.profile)
+        .await
+        .map_err(SystemError::Config)?;
+    let mut report = format!("Created snapshot {} ({} bytes)", snapshot.id, snapshot.size);
+
+    for id in prune(target, root, retention)? {
+        report.push_str(&format!("\nPruned snapshot {}", id));
+    }
+
+    Ok(report)
+}
+
+// Restoring underneath a running Firefox would be overwritten on its next
+// write, so the container must not exist at all
+pub async fn restore(runtime: &Runtime, target: &Target, root: &Path, id: Option<&str>) -> Result<String, SystemError> {
+    let state = status(runtime, target).await?;
+    if state != ContainerStatus::NotFound {
+        return Err(SystemError::InvalidState(format!(
+            "Container {} is {}; stop it before restoring a snapshot",
+            target.container_name, state
+        )));
+    }
+
+    snapshot::restore_snapshot(root, &target.container_name, &target.profile, id)
+        .await
+        .map_err(SystemError::Config)
+}
+
+pub fn prune(target: &Target, root: &Path, retention: &RetentionPolicy) -> Result<Vec<String>, SystemError> {
+    snapshot::prune_snapshots(root, &target.container_name, retention).map_err(SystemError::Config)
+}
+
+// Deleting is not undoable, so keep a last snapshot of the profile unless told otherwise
+pub async fn delete(runtime: &Runtime, target: &Target, root: &Path, take_snapshot: bool) -> Result<String, SystemError> {
+    let state = status(runtime, target).await?;
+    if state != ContainerStatus::NotFound {
+        return Err(SystemError::InvalidState(format!(
+            "Container {} is {}; stop it before deleting the profile",
+            target.container_name, state
+        )));
+    }
+
+    let mut report = String::new();
+    if take_snapshot && Path::new(&target.profile).is_dir() {
+        let snapshot = snapshot::create_snapshot(root, &target.container_name, &target.profile)
+            .await
+            .map_err(SystemError::Config)?;
+        report.push_str(&format!("Created snapshot {} before deleting\n", snapshot.id));
+    }
+
+    profile::delete_profile(&target.profile).map_err(SystemError::Config)?;
+    report.push_str(&format!("Deleted profile {}", target.profile));
+    Ok(report)
+}
+
+// An operation that has nothing to do: report it, or fail with --strict
+fn nothing_to_do(strict: bool, message: String) -> Result<String, SystemError> {
+    if strict {
+        Err(SystemError::InvalidState(message))
+    } else {
+        Ok(message)
+    }
+}
+
+// A container left in a state the operation cannot start from
+fn wrong_state(target: &Target, state: &ContainerStatus, action: &str) -> SystemError {
+    SystemError::InvalidState(format!("Container {} is {}; cannot {} it", target.container_name, state, action))
+}
+
+// Run a Menu_Runner_system function by name with its trailing option flags,
+// returning what the command line tool prints on success. The container state
+// is checked first: operations that are already done succeed with a message
+// and a frozen container is unfrozen where needed, unless --strict is given.
+pub async fn run_action(runtime: &Runtime, target: &Target, action: &str, flags: &[String]) -> Result<String, SystemError> {
+    let strict = flags.iter().any(|flag| flag == "--strict");
+    let snapshot_root = flag_value(flags, "--snapshot-dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(snapshot::default_snapshot_root);
+    let retention = || -> Result<RetentionPolicy, SystemError> {
+        Ok(RetentionPolicy {
+            keep: parse_flag(flags, "--keep")
+                .map_err(SystemError::InvalidArgument)?
+                .unwrap_or(DEFAULT_SNAPSHOT_KEEP),
+            max_age_days: parse_flag(flags, "--max-age").map_err(SystemError::InvalidArgument)?,
+        })
+    };
+
+    match action {
+        "start" => {
+            let mut report = String::new();
+            match status(runtime, target).await? {
+                ContainerStatus::NotFound => {}
+                ContainerStatus::Running => {
+                    return nothing_to_do(strict, format!("{} is already running", target.container_name))
+                }
+                ContainerStatus::Paused if !strict => {
+                    unfreeze(runtime, target).await?;
+                    return Ok(format!("{} was frozen; unfroze it", target.container_name));
+                }
+                ContainerStatus::Exited if !strict => {
+                    remove(runtime, target).await?;
+                    report.push_str(&format!("Removed exited container {}\n", target.container_name));
+                }
+                state => return Err(wrong_state(target, &state, "start")),
+            }
+
+            let options = StartOptions::from_flags(&target.app, flags)?;
+            start(runtime, target, &options).await?;
+            report.push_str(&format!("Started {}", target.container_name));
+            Ok(report)
+        }
+        "status" => {
+            let report = runtime
+                .describe(&target.container_name)
+                .await
+                .map_err(SystemError::Runtime)?;
+            Ok(report)
+        }
+        "freeze" => match status(runtime, target).await? {
+            ContainerStatus::Running => {
+                freeze(runtime, target).await?;
+                Ok(format!("Froze {}", target.container_name))
+            }
+            ContainerStatus::Paused => nothing_to_do(strict, format!("{} is already frozen", target.container_name)),
+            state if !state.is_alive() => {
+                nothing_to_do(strict, format!("{} is {}; nothing to freeze", target.container_name, state))
+            }
+            state => Err(wrong_state(target, &state, "freeze")),
+        },
+        "unfreeze" => match status(runtime, target).await? {
+            ContainerStatus::Paused => {
+                unfreeze(runtime, target).await?;
+                Ok(format!("Unfroze {}", target.container_name))
+            }
+            ContainerStatus::Running => nothing_to_do(strict, format!("{} is not frozen", target.container_name)),
+            state if !state.is_alive() => {
+                nothing_to_do(strict, format!("{} is {}; nothing to unfreeze", target.container_name, state))
+            }
+            state => Err(wrong_state(target, &state, "unfreeze")),
+        },
+        "stop" => {
+            let grace_secs = parse_flag::<u64>(flags, "--grace")
+                .map_err(SystemError::InvalidArgument)?
+                .unwrap_or(DEFAULT_STOP_GRACE_SECS);
+
+            // A frozen container cannot handle SIGTERM, so thaw it first
+            let mut report = String::new();
+            match status(runtime, target).await? {
+                ContainerStatus::Running => {}
+                ContainerStatus::Paused if !strict => {
+                    unfreeze(runtime, target).await?;
+                    report.push_str(&format!("Unfroze {}\n", target.container_name));
+                }
+                state if !state.is_alive() => {
+                    return nothing_to_do(strict, format!("{} is already stopped", target.container_name))
+                }
+                state => return Err(wrong_state(target, &state, "stop")),
+            }
+
+            match stop(runtime, target, Duration::from_secs(grace_secs)).await? {
+                StopOutcome::Exited => report.push_str(&format!("Stopped {}", target.container_name)),
+                StopOutcome::Killed => report.push_str(&format!(
+                    "Container {} did not exit within {}s and was killed",
+                    target.container_name, grace_secs
+                )),
+            }
+            Ok(report)
+        }
+        // force_kill is the menu's name for the immediate kill
+        "kill" | "force_kill" => {
+            let state = status(runtime, target).await?;
+            if !state.is_alive() {
+                return nothing_to_do(strict, format!("{} is {}; nothing to kill", target.container_name, state));
+            }
+
+            kill(runtime, target).await?;
+            Ok(format!("Killed {}", target.container_name))
+        }
+        "snapshot" => {
+            // A running container is refused unless --freeze asks to freeze
+            // it for the length of the snapshot
+            let freeze_running = flags.iter().any(|flag| flag == "--freeze");
+            if !freeze_running || status(runtime, target).await? != ContainerStatus::Running {
+                return take_snapshot(runtime, target, &snapshot_root, &retention()?).await;
+            }
+
+            freeze(runtime, target).await?;
+            let result = take_snapshot(runtime, target, &snapshot_root, &retention()?).await;
+            let name = &target.container_name;
+            match (result, unfreeze(runtime, target).await) {
+                (Ok(report), Ok(())) => Ok(format!("Froze {} for the snapshot\n{}", name, report)),
+                (Ok(report), Err(e)) => Err(SystemError::Runtime(format!("{}\n{} is still frozen: {}", report, name, e))),
+                (Err(e), Ok(())) => Err(e),
+                (Err(e), Err(thaw)) => Err(SystemError::Runtime(format!("{}\n{} is still frozen: {}", e, name, thaw))),
+            }
+        }
+        "snapshots" => {
+            let snapshots = snapshot::list_snapshots(&snapshot_root, &target.container_name)
+                .map_err(SystemError::Config)?;
+            if snapshots.is_empty() {
+                return Ok(format!("No snapshots for {}", target.container_name));
+            }
+
+            let lines: Vec<String> = snapshots
+                .iter()
+                .map(|s| format!("{}  {:>12} bytes  {}", s.id, s.size, s.path.display()))
+                .collect();
+            Ok(lines.join("\n"))
+        }
+        "restore" => {
+            // An optional snapshot id may precede the option flags
+            let id = flags.first().filter(|arg| !arg.starts_with("--"));
+            if !strict && status(runtime, target).await? == ContainerStatus::Exited {
+                remove(runtime, target).await?;
+            }
+            let restored = restore(runtime, target, &snapshot_root, id.map(String::as_str)).await?;
+            Ok(format!("Restored snapshot {} into {}", restored, target.profile))
+        }
+        "prune" => {
+            let removed = prune(target, &snapshot_root, &retention()?)?;
+            let lines: Vec<String> = removed.iter().map(|id| format!("Pruned snapshot {}", id)).collect();
+            Ok(lines.join("\n"))
+        }
+        "create" => {
+            let template = flag_value(flags, "--from").map(|t| t.trim_end_matches('/'));
+            if Path::new(&target.profile).is_dir() {
+                return nothing_to_do(strict, format!("Profile {} already exists", target.profile));
+            }
+
+            profile::create_profile(&target.profile, template)
+                .await
+                .map_err(SystemError::Config)?;
+            match template {
+                Some(template) => Ok(format!("Created profile {} from {}", target.profile, template)),
+                None => Ok(format!("Created profile {}", target.profile)),
+            }
+        }
+        "delete" => {
+            let take_snapshot = !flags.iter().any(|flag| flag == "--no-snapshot");
+            if !Path::new(&target.profile).exists() {
+                return nothing_to_do(strict, format!("Profile {} does not exist", target.profile));
+            }
+            if !strict && status(runtime, target).await? == ContainerStatus::Exited {
+                remove(runtime, target).await?;
+            }
+            delete(runtime, target, &snapshot_root, take_snapshot).await
+        }
+        _ => Err(SystemError::InvalidArgument(format!("Unknown function: {}", action))),
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+
+use crate::cli::{flag_value, flag_values, parse_flag};
+
+// Resource limits applied to a profile container through `docker run`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResourceLimits {
+    #[serde(default)]
+    pub cpus: Option<String>,
+    #[serde(default)]
+    pub memory: Option<String>,
+    #[serde(default)]
+    pub pids_limit: Option<u32>,
+    #[serde(default)]
+    pub blkio_weight: Option<u16>,
+    #[serde(default)]
+    pub device_read_bps: Vec<String>,
+    #[serde(default)]
+    pub device_write_bps: Vec<String>,
+}
+
+impl ResourceLimits {
+    pub fn from_flags(flags: &[String]) -> Result<Self, String> {
+        Ok(ResourceLimits {
+            cpus: flag_value(flags, "--cpus").map(str::to_string),
+            memory: flag_value(flags, "--memory").map(str::to_string),
+            pids_limit: parse_flag(flags, "--pids-limit")?,
+            blkio_weight: parse_flag(flags, "--blkio-weight")?,
+            device_read_bps: flag_values(flags, "--device-read-bps"),
+            device_write_bps: flag_values(flags, "--device-write-bps"),
+        })
+    }
+
+    // Limits given on the command line win over the ones from the app definition
+    pub fn merged_over(self, defaults: &ResourceLimits) -> Self {
+        ResourceLimits {
+            cpus: self.cpus.or_else(|| defaults.cpus.clone()),
+            memory: self.memory.or_else(|| defaults.memory.clone()),
+            pids_limit: self.pids_limit.or(defaults.pids_limit),
+            blkio_weight: self.blkio_weight.or(defaults.blkio_weight),
+            device_read_bps: if self.device_read_bps.is_empty() {
+                defaults.device_read_bps.clone()
+            } else {
+                self.device_read_bps
+            },
+            device_write_bps: if self.device_write_bps.is_empty() {
+                defaults.device_write_bps.clone()
+            } else {
+                self.device_write_bps
+            },
+        }
+    }
+
+    pub fn to_docker_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if let Some(cpus) = &self.cpus {
+            args.push(format!("--cpus={}", cpus));
+        }
+        if let Some(memory) = &self.memory {
+            args.push(format!("--memory={}", memory));
+        }
+        if let Some(pids_limit) = self.pids_limit {
+            args.push(format!("--pids-limit={}", pids_limit));
+        }
+        if let Some(blkio_weight) = self.blkio_weight {
+            args.push(format!("--blkio-weight={}", blkio_weight));
+        }
+        for rate in &self.device_read_bps {
+            args.push(format!("--device-read-bps={}", rate));
+        }
+        for rate in &self.device_write_bps {
+            args.push(format!("--device-write-bps={}", rate));
+        }
+
+        args
+    }
+}
+
+// Parse a Docker-style size such as 512m, 4g or 10mb into bytes
+pub fn parse_bytes(value: &str) -> Result<i64, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let number_end = lower
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(number_end);
+
+    let number: f64 = number.parse().map_err(|_| format!("Invalid size: {}", value))?;
+    let multiplier: f64 = match unit.trim_end_matches('b') {
+        "" => 1.0,
+        "k" => 1024.0,
+        "m" => 1024.0 * 1024.0,
+        "g" => 1024.0 * 1024.0 * 1024.0,
+        "t" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("Invalid size unit: {}", value)),
+    };
+
+    Ok((number * multiplier) as i64)
+}
@@ -1,192 +1,87 @@
 use std::env;
-use std::path::Path;
-use tokio::process::Command;
+
+use Menu_Runner_system::{run_action, Runtime, Target};
 
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
 
+    // `events [<app>]` watches containers rather than acting on one profile
+    if args.get(1).map(String::as_str) == Some("events") {
+        let app = args.get(2).filter(|arg| !arg.starts_with("--"));
+        let flags = &args[2..];
+        let runtime = select_runtime(flags);
+        app_events(&runtime, app.map(String::as_str)).await;
+        return;
+    }
+
     if args.len() < 4 {
-        eprintln!("Usage: {} <start|status|freeze|unfreeze|kill> <app> <profile>", args[0]);
+        eprintln!("Usage: {} <start|status|freeze|unfreeze|stop|kill|force_kill> <app> <profile> [options]", args[0]);
+        eprintln!("       {} <snapshot|snapshots|prune> <app> <profile> [options]", args[0]);
+        eprintln!("       {} restore <app> <profile> [<snapshot-id>] [options]", args[0]);
+        eprintln!("       {} create <app> <profile> [--from <template-profile>]", args[0]);
+        eprintln!("       {} delete <app> <profile> [--no-snapshot]", args[0]);
+        eprintln!("       {} events [<app>] [options]", args[0]);
+        eprintln!("Options:");
+        eprintln!("  --grace <seconds>            time allowed after SIGTERM before stop kills");
+        eprintln!("  --app-config <path>          app definition to use instead of searching configs/apps,");
+        eprintln!("                               ~/.config/menu_runner/apps and /etc/menu_runner/apps");
+        eprintln!("  --cpus <n>                   CPU quota for the container");
+        eprintln!("  --memory <size>              memory limit, e.g. 2g");
+        eprintln!("  --pids-limit <n>             maximum number of processes");
+        eprintln!("  --blkio-weight <10-1000>     relative block I/O weight");
+        eprintln!("  --device-read-bps <dev:rate> read rate limit, may be repeated");
+        eprintln!("  --device-write-bps <dev:rate> write rate limit, may be repeated");
+        eprintln!("  --network <mode>             none, host, default, proxy:<container> or a network name");
+        eprintln!("  --downloads <dir>            host directory mounted as the container's Downloads folder");
+        eprintln!("  --downloads-per-profile      use a subdirectory of --downloads for each profile");
+        eprintln!("  --clipboard <policy>         shared or isolated Firefox clipboard behaviour");
+        eprintln!("  --snapshot-dir <path>        where profile snapshots are stored");
+        eprintln!("  --freeze                     freeze a running container for the length of a snapshot");
+        eprintln!("  --keep <n>                   snapshots to keep when pruning (default 10)");
+        eprintln!("  --max-age <days>             prune snapshots older than this");
+        eprintln!("  --from <template-profile>    profile directory to copy when creating");
+        eprintln!("  --no-snapshot                delete without taking a final snapshot");
+        eprintln!("  --strict                     fail instead of skipping operations that are already done");
+        eprintln!("  --backend <cli|api|native>   spawn the docker CLI (default), use the Engine API socket or run without Docker");
+        eprintln!("  --docker-socket <path>       API socket, default from DOCKER_HOST or /var/run/docker.sock");
         std::process::exit(1);
     }
 
     let func = &args[1];
-    let app = &args[2];
-    let profile = args[3].trim_end_matches('/').to_string();
+    let target = Target::new(&args[2], &args[3]).unwrap_or_else(|e| show_error(&e.to_string()));
 
-    let profile_part = Path::new(&profile).file_name().unwrap().to_str().unwrap();
-    let container_name = format!("app.{}-{}", app, profile_part);
-    let container_profile_path = "/home/firefoxuser/profile";
-    let display = env::var("DISPLAY").unwrap();
+    let flags = &args[4..];
+    let runtime = select_runtime(flags);
 
-    match func.as_str() {
-        "start" => app_start(&container_name, &profile, container_profile_path, &display).await,
-        "status" => app_status(&container_name).await,
-        "freeze" => app_freeze(&container_name).await,
-        "unfreeze" => app_unfreeze(&container_name).await,
-        "kill" => app_kill(&container_name).await,
-        _ => show_error(&format!("Unknown function: {}", func)),
+    match run_action(&runtime, &target, func, flags).await {
+        Ok(report) if !report.is_empty() => println!("{}", report),
+        Ok(_) => {}
+        Err(e) => show_error(&e.to_string()),
     }
 }
-//
 
-async fn app_start(container_name: &str, profile: &str, container_profile_path: &str, display: &str) {
-    let user_id = users::get_current_uid();
-    let status = Command::new("docker")
-        .args([
-            "run",
-            "-d",
-            "--rm",
-            "--name",
-            container_name,
-            "-e",
-            &format!("DISPLAY={}", display),
-            "-v",
-            "/tmp/.X11-unix:/tmp/.X11-unix:z",
-            "-v",
-            &format!("/run/user/{}/pulse:/run/user/{}/pulse", user_id, user_id),
-            "-v",
-            "/etc/machine-id:/etc/machine-id",
-            "-e",
-            &format!("PULSE_SERVER=unix:/run/user/{}/pulse/native", user_id),
-            "-v",
-            &format!("{}:{}", profile, container_profile_path),
-            "--user",
-            &format!("{}:{}", user_id, users::get_current_gid()),
-            "fedora-x11-test",
-            "firefox",
-            "-profile",
-            container_profile_path,
-        ])
-        .status()
-        .await
-        .expect("Failed to execute Docker command");
-
-    if !status.success() {
-        show_error("Failed to start container");
-    }
+fn select_runtime(flags: &[String]) -> Runtime {
+    Runtime::from_flags(flags).unwrap_or_else(|e| show_error(&e))
 }
 
-//
-
-//
-// async fn app_start(container_name: &str, profile: &str, container_profile_path: &str, display: &str) {
-//     let user_id = users::get_current_uid();
-//     let status = Command::new("docker")
-//         .args([
-//             "run",
-//             "-d",
-//             "--rm",
-//             "--name",
-//             container_name,
-//             "-e",
-//             &format!("DISPLAY={}", display),
-//             "-v",
-//             "/tmp/.X11-unix:/tmp/.X11-unix:z",
-//             "-v",
-//             &format!("/run/user/{}/pulse:/run/user/{}/pulse", user_id, user_id),
-//             "-v",
-//             "/etc/machine-id:/etc/machine-id",
-//             "-e",
-//             &format!("PULSE_SERVER=unix:/run/user/{}/pulse/native", user_id),
-//             "-v",
-//             &format!("{}:{}", profile, container_profile_path),
-//             "--user",
-//             &format!("{}:{}", user_id, users::get_current_gid()),
-//             "fedora-x11-test",
-//             "firefox",
-//             "-profile",
-//             container_profile_path,
-//         ])
-//         .status()
-//         .await
-//         .expect("Failed to execute Docker command");
-// 
-//     if !status.success() {
-//         show_error("Failed to start container");
-//     }
-// }
-// //
-// async fn app_start(container_name: &str, profile: &str, container_profile_path: &str, display: &str) {
-//     let status = Command::new("docker")
-//         .args([
-//             "run",
-//             "-d",
-//             "--rm",
-//             "--name",
-//             container_name,
-//             "-e",
-//             &format!("DISPLAY={}", display),
-//             "-v",
-//             "/tmp/.X11-unix:/tmp/.X11-unix:z",
-//             "-v",
-//             &format!("{}:{}", profile, container_profile_path),
-//             "--user",
-//             &format!("{}:{}", users::get_current_uid(), users::get_current_gid()),
-//             "fedora-x11-test",
-//             "firefox",
-//             "-profile",
-//             container_profile_path,
-//         ])
-//         .status()
-//         .await
-//         .expect("Failed to execute Docker command");
-// 
-//     if !status.success() {
-//         show_error("Failed to start container");
-//     }
-// }
-
-async fn app_status(container_name: &str) {
-    let output = Command::new("docker")
-        .args(["ps", "-a", "--filter", &format!("name={}", container_name)])
-        .output()
-        .await
-        .expect("Failed to execute Docker command");
-
-    println!("{}", String::from_utf8_lossy(&output.stdout));
-}
-
-async fn app_freeze(container_name: &str) {
-    let status = Command::new("docker")
-        .args(["pause", container_name])
-        .status()
-        .await
-        .expect("Failed to execute Docker command");
-
-    if !status.success() {
-        show_error("Failed to freeze container");
+// Print one line per container event, e.g. "pause app.firefox-0.Github",
+// for the containers Menu_Runner_system manages
+async fn app_events(runtime: &Runtime, app: Option<&str>) {
+    let prefix = match app {
+        Some(app) => format!("app.{}-", app),
+        None => "app.".to_string(),
+    };
+
+    let mut events = runtime.events().await.unwrap_or_else(|e| show_error(&e));
+    while let Some(event) = events.next_event().await.unwrap_or_else(|e| show_error(&e)) {
+        if event.container.starts_with(&prefix) {
+            println!("{} {}", event.action, event.container);
+        }
     }
 }
 
-async fn app_unfreeze(container_name: &str) {
-    let status = Command::new("docker")
-        .args(["unpause", container_name])
-        .status()
-        .await
-        .expect("Failed to execute Docker command");
-
-    if !status.success() {
-        show_error("Failed to unfreeze container");
-    }
-}
-
-async fn app_kill(container_name: &str) {
-    let status = Command::new("docker")
-        .args(["kill", container_name])
-        .status()
-        .await
-        .expect("Failed to execute Docker command");
-
-    if !status.success() {
-        show_error("Failed to kill container");
-    }
-}
-
-fn show_error(message: &str) {
+fn show_error(message: &str) -> ! {
     eprintln!("{}", message);
     std::process::exit(1);
 }
-
-
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::engine::EngineEvent;
+use crate::spec::ContainerSpec;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+// How often the native event stream looks at the running apps
+const EVENT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Runs apps as plain host processes instead of containers. Each app gets its
+// own process group and, where the user's cgroup is delegated (e.g. a systemd
+// user session), its own cgroup v2 group so it can be frozen with
+// cgroup.freeze; otherwise freezing falls back to SIGSTOP/SIGCONT on the
+// process group.
+//
+// What the app is running as is kept in a small state file per app, so a
+// later Menu_Runner_system invocation can find it again.
+#[derive(Debug, Clone)]
+pub struct NativeBackend {
+    state_dir: PathBuf,
+}
+
+// A launched app: its process group leader, when that process started and
+// the cgroup it runs in. The start time tells the app apart from a later
+// process that got the same pid.
+struct NativeApp {
+    pid: u32,
+    start_time: u64,
+    cgroup: Option<PathBuf>,
+}
+
+impl NativeBackend {
+    pub fn new(state_dir: impl Into<PathBuf>) -> Self {
+        NativeBackend { state_dir: state_dir.into() }
+    }
+
+    // State lives under $XDG_RUNTIME_DIR so it goes away with the session,
+    // like the processes it describes
+    pub fn detect() -> Self {
+        let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
+            .unwrap_or_else(|_| format!("/tmp/Menu_Runner-{}", users::get_current_uid()));
+        NativeBackend::new(Path::new(&runtime_dir).join("Menu_Runner/native"))
+    }
+
+    // Launch the spec's command on the host. Container paths in the command
+    // are mapped back through the spec's bind mounts, e.g. the profile mount.
+    // Container-only settings (image, limits, network, security) do not apply.
+    pub async fn run(&self, spec: &ContainerSpec) -> Result<(), String> {
+        let (program, args) = spec
+            .command
+            .split_first()
+            .ok_or_else(|| format!("No command to run for {}", spec.name))?;
+
+        fs::create_dir_all(&self.state_dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.state_dir.display(), e))?;
+        // The previous run's log is kept next to the new one
+        let log_path = self.state_dir.join(format!("{}.log", spec.name));
+        if log_path.exists() {
+            let _ = fs::rename(&log_path, self.state_dir.join(format!("{}.log.1", spec.name)));
+        }
+        let log = fs::File::create(&log_path)
+            .map_err(|e| format!("Failed to create {}: {}", log_path.display(), e))?;
+        let log_err = log.try_clone().map_err(|e| e.to_string())?;
+
+        let mut command = Command::new(host_path(program, &spec.binds));
+        command
+            .args(args.iter().map(|arg| host_path(arg, &spec.binds)))
+            .envs(spec.env.iter().filter_map(|var| var.split_once('=')))
+            .stdin(Stdio::null())
+            .stdout(log)
+            .stderr(log_err)
+            .process_group(0);
+
+        // The app joins its own cgroup before it runs, so no child it starts
+        // can escape the freezer. Writing 0 to cgroup.procs moves the writer;
+        // the file is opened here since the child may not allocate.
+        let procs = create_cgroup(&spec.name).and_then(|cgroup| {
+            let procs = fs::OpenOptions::new().write(true).open(cgroup.join("cgroup.procs")).ok()?;
+            Some((cgroup, procs))
+        });
+        let cgroup = procs.map(|(cgroup, procs)| {
+            // SAFETY: only writes to an already open file between fork and exec
+            unsafe {
+                command.pre_exec(move || {
+                    let _ = (&procs).write_all(b"0");
+                    Ok(())
+                });
+            }
+            cgroup
+        });
+
+        let child = command
+            .spawn()
+            .map_err(|e| format!("Failed to execute {}: {}", program, e))?;
+        let pid = child.id().ok_or_else(|| format!("{} exited immediately", program))?;
+        let start_time = process_start_time(pid).ok_or_else(|| format!("{} exited immediately", program))?;
+
+        // A move the cgroup refused leaves freezing to signals
+        let cgroup = cgroup.filter(|cgroup| {
+            fs::read_to_string(cgroup.join("cgroup.procs"))
+                .is_ok_and(|procs| procs.lines().any(|line| line == pid.to_string()))
+        });
+
+        self.save(&spec.name, &NativeApp { pid, start_time, cgroup })
+    }
+
+    pub async fn pause(&self, name: &str) -> Result<(), String> {
+        let app = self.running(name)?;
+        match &app.cgroup {
+            Some(cgroup) => write_cgroup(cgroup, "cgroup.freeze", "1"),
+            None => signal_group(app.pid, "SIGSTOP").await,
+        }
+    }
+
+    pub async fn unpause(&self, name: &str) -> Result<(), String> {
+        let app = self.running(name)?;
+        match &app.cgroup {
+            Some(cgroup) => write_cgroup(cgroup, "cgroup.freeze", "0"),
+            None => signal_group(app.pid, "SIGCONT").await,
+        }
+    }
+
+    // Signal the whole process group; a SIGKILL also goes to every process
+    // in the app's cgroup, catching children that left the group
+    pub async fn kill(&self, name: &str, signal: &str) -> Result<(), String> {
+        let app = self.running(name)?;
+        signal_group(app.pid, signal).await?;
+
+        if let (Some(cgroup), "SIGKILL" | "KILL" | "9") = (&app.cgroup, signal) {
+            let _ = write_cgroup(cgroup, "cgroup.kill", "1");
+        }
+        Ok(())
+    }
+
+    // running or paused like Docker reports it, or None once the app is gone
+    pub async fn state(&self, name: &str) -> Result<Option<String>, String> {
+        let Some(app) = self.load(name) else {
+            return Ok(None);
+        };
+
+        let Some(process_state) = app.process_state() else {
+            self.forget(name, &app);
+            return Ok(None);
+        };
+
+        let frozen = match &app.cgroup {
+            Some(cgroup) => fs::read_to_string(cgroup.join("cgroup.events"))
+                .map(|events| events.lines().any(|line| line == "frozen 1"))
+                .unwrap_or(false),
+            None => process_state == 'T',
+        };
+
+        Ok(Some(if frozen { "paused" } else { "running" }.to_string()))
+    }
+
+    pub async fn describe(&self, name: &str) -> Result<String, String> {
+        let state = self.state(name).await?;
+        match (state, self.load(name)) {
+            (Some(state), Some(app)) => Ok(format!(
+                "{}: {} (pid {}, {})",
+                name,
+                state,
+                app.pid,
+                match &app.cgroup {
+                    Some(cgroup) => format!("cgroup {}", cgroup.display()),
+                    None => "process group".to_string(),
+                }
+            )),
+            _ => Ok(format!("{}: not running", name)),
+        }
+    }
+
+    // Native apps are removed as soon as they exit, like `docker run --rm`
+    pub async fn remove(&self, name: &str) -> Result<(), String> {
+        if let Some(app) = self.load(name) {
+            if app.process_state().is_some() {
+                return Err(format!("{} is still running", name));
+            }
+            self.forget(name, &app);
+        }
+        Ok(())
+    }
+
+    pub async fn events(&self) -> Result<NativeEvents, String> {
+        let mut events = NativeEvents {
+            backend: self.clone(),
+            known: HashMap::new(),
+            pending: Vec::new(),
+        };
+        // Only report changes from here on
+        events.poll().await;
+        events.pending.clear();
+        Ok(events)
+    }
+
+    fn state_file(&self, name: &str) -> PathBuf {
+        self.state_dir.join(name)
+    }
+
+    // State file: the pid on the first line, the cgroup path (if any) on the
+    // second and the process start time on the third
+    fn save(&self, name: &str, app: &NativeApp) -> Result<(), String> {
+        let cgroup = app.cgroup.as_ref().map(|c| c.display().to_string()).unwrap_or_default();
+        let path = self.state_file(name);
+        fs::write(&path, format!("{}\n{}\n{}\n", app.pid, cgroup, app.start_time))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    fn load(&self, name: &str) -> Option<NativeApp> {
+        let content = fs::read_to_string(self.state_file(name)).ok()?;
+        let mut lines = content.lines();
+        let pid = lines.next()?.trim().parse().ok()?;
+        let cgroup = lines.next().filter(|line| !line.is_empty()).map(PathBuf::from);
+        let start_time = lines.next()?.trim().parse().ok()?;
+        Some(NativeApp { pid, start_time, cgroup })
+    }
+
+    fn running(&self, name: &str) -> Result<NativeApp, String> {
+        self.load(name)
+            .filter(|app| app.process_state().is_some())
+            .ok_or_else(|| format!("No such app: {}", name))
+    }
+
+    // The log stays for a look at why the app exited
+    fn forget(&self, name: &str, app: &NativeApp) {
+        let _ = fs::remove_file(self.state_file(name));
+        if let Some(cgroup) = &app.cgroup {
+            let _ = fs::remove_dir(cgroup);
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        fs::read_dir(&self.state_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .filter(|name| !name.ends_with(".log") && !name.ends_with(".log.1"))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl NativeApp {
+    // The process's state, or None once it is gone or its pid belongs to
+    // another process
+    fn process_state(&self) -> Option<char> {
+        let state = process_state(self.pid)?;
+        (process_start_time(self.pid) == Some(self.start_time)).then_some(state)
+    }
+}
+
+// Events for native apps, found by polling their state since there is no
+// daemon to report them
+pub struct NativeEvents {
+    backend: NativeBackend,
+    known: HashMap<String, String>,
+    pending: Vec<EngineEvent>,
+}
+
+impl NativeEvents {
+    pub async fn next_event(&mut self) -> Result<Option<EngineEvent>, String> {
+        while self.pending.is_empty() {
+            tokio::time::sleep(EVENT_POLL_INTERVAL).await;
+            self.poll().await;
+        }
+        Ok(Some(self.pending.remove(0)))
+    }
+
+    // Compare every app's state with the last poll, queueing the Docker
+    // event each change corresponds to
+    async fn poll(&mut self) {
+        let mut names = self.backend.names();
+        names.extend(self.known.keys().cloned());
+        names.sort();
+        names.dedup();
+
+        for name in names {
+            let state = self.backend.state(&name).await.ok().flatten();
+            let previous = self.known.get(&name).map(String::as_str);
+            let action = match (previous, state.as_deref()) {
+                (None, Some("running")) => Some("start"),
+                (None, Some("paused")) => Some("pause"),
+                (Some("running"), Some("paused")) => Some("pause"),
+                (Some("paused"), Some("running")) => Some("unpause"),
+                (Some(_), None) => Some("die"),
+                _ => None,
+            };
+
+            if let Some(action) = action {
+                self.pending.push(EngineEvent { action: action.to_string(), container: name.clone() });
+            }
+            match state {
+                Some(state) => self.known.insert(name, state),
+                None => self.known.remove(&name),
+            };
+        }
+    }
+}
+
+// Map a container path in a command argument to the host path bound there
+fn host_path(arg: &str, binds: &[String]) -> String {
+    for bind in binds {
+        let mut parts = bind.split(':');
+        let (Some(host), Some(container)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+
+        if arg == container {
+            return host.to_string();
+        }
+        if let Some(rest) = arg.strip_prefix(container).filter(|rest| rest.starts_with('/')) {
+            return format!("{}{}", host, rest);
+        }
+    }
+    arg.to_string()
+}
+
+// The state letter from /proc/<pid>/stat (R, S, T, ...), or None when the
+// process is gone or only a zombie is left
+fn process_state(pid: u32) -> Option<char> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name is in parentheses and may itself contain spaces
+    let state = stat.rsplit_once(')')?.1.trim_start().chars().next()?;
+    (state != 'Z' && state != 'X').then_some(state)
+}
+
+// When the process started, in clock ticks after boot: field 22 of
+// /proc/<pid>/stat, the 20th after the command name
+fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    stat.rsplit_once(')')?.1.split_whitespace().nth(19)?.parse().ok()
+}
+
+// A new cgroup next to the one Menu_Runner_system runs in. Processes may only
+// be moved between cgroups the user can write to, so this only works inside a
+// delegated subtree; None means freezing falls back to signals.
+fn create_cgroup(name: &str) -> Option<PathBuf> {
+    let own = fs::read_to_string("/proc/self/cgroup").ok()?;
+    // cgroup v2 has a single "0::/path" line
+    let own_path = own.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let own_dir = Path::new(CGROUP_ROOT).join(own_path.trim_start_matches('/'));
+    let parent = own_dir.parent()?;
+
+    let cgroup = parent.join(format!("menu-runner-{}", name));
+    if !cgroup.is_dir() {
+        fs::create_dir(&cgroup).ok()?;
+    }
+    cgroup.join("cgroup.freeze").exists().then_some(cgroup)
+}
+
+fn write_cgroup(cgroup: &Path, file: &str, value: &str) -> Result<(), String> {
+    let path = cgroup.join(file);
+    fs::write(&path, value).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+async fn signal_group(pid: u32, signal: &str) -> Result<(), String> {
+    let signal = signal.trim_start_matches("SIG");
+    let status = Command::new("kill")
+        .arg(format!("-{}", signal))
+        .arg("--")
+        .arg(format!("-{}", pid))
+        .status()
+        .await
+        .map_err(|e| format!("Failed to execute kill: {}", e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("kill exited with {}", status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::ResourceLimits;
+    use crate::network::NetworkPolicy;
+    use crate::security::SecurityOptions;
+
+    // A backend with a state directory of its own, removed afterwards
+    struct TestBackend(NativeBackend);
+
+    impl TestBackend {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("menu-runner-native-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&dir);
+            TestBackend(NativeBackend::new(dir))
+        }
+    }
+
+    impl Drop for TestBackend {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.state_dir);
+        }
+    }
+
+    fn spec(name: &str, command: &[&str]) -> ContainerSpec {
+        ContainerSpec {
+            name: name.to_string(),
+            image: String::new(),
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            env: Vec::new(),
+            binds: Vec::new(),
+            user: String::new(),
+            auto_remove: true,
+            limits: ResourceLimits::default(),
+            network: NetworkPolicy::Default,
+            security: SecurityOptions::default(),
+        }
+    }
+
+    #[test]
+    fn container_paths_map_to_their_bind() {
+        let binds = vec!["/home/me/profiles/work:/home/firefoxuser/profile:rw".to_string()];
+
+        assert_eq!(host_path("/home/firefoxuser/profile", &binds), "/home/me/profiles/work");
+        assert_eq!(host_path("/home/firefoxuser/profile/prefs.js", &binds), "/home/me/profiles/work/prefs.js");
+        assert_eq!(host_path("/home/firefoxuser/profile2", &binds), "/home/firefoxuser/profile2");
+        assert_eq!(host_path("--no-remote", &binds), "--no-remote");
+    }
+
+    #[test]
+    fn own_process_has_a_state_and_start_time() {
+        let pid = std::process::id();
+
+        assert!(process_state(pid).is_some());
+        assert!(process_start_time(pid).is_some());
+    }
+
+    #[tokio::test]
+    async fn a_reused_pid_is_not_the_app() {
+        let backend = TestBackend::new("reused");
+        fs::create_dir_all(&backend.0.state_dir).unwrap();
+        fs::write(backend.0.state_dir.join("work.log"), "last words\n").unwrap();
+
+        // Our own pid, but a start time it never had
+        let pid = std::process::id();
+        let start_time = process_start_time(pid).unwrap() + 1;
+        backend.0.save("work", &NativeApp { pid, start_time, cgroup: None }).unwrap();
+
+        assert_eq!(backend.0.state("work").await.unwrap(), None);
+        assert!(backend.0.kill("work", "SIGTERM").await.is_err());
+        assert!(backend.0.load("work").is_none());
+        assert!(backend.0.state_dir.join("work.log").exists());
+    }
+
+    #[tokio::test]
+    async fn apps_run_until_killed_and_keep_their_last_log() {
+        let backend = TestBackend::new("lifecycle");
+
+        backend.0.run(&spec("work", &["sh", "-c", "echo first; exec sleep 30"])).await.unwrap();
+        assert_eq!(backend.0.state("work").await.unwrap().as_deref(), Some("running"));
+
+        // Let it write its log before killing it
+        let log = backend.0.state_dir.join("work.log");
+        for _ in 0..50 {
+            if fs::read_to_string(&log).unwrap_or_default() == "first\n" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        backend.0.kill("work", "SIGKILL").await.unwrap();
+        for _ in 0..50 {
+            if backend.0.state("work").await.unwrap().is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(backend.0.state("work").await.unwrap(), None);
+        assert_eq!(fs::read_to_string(&log).unwrap(), "first\n");
+
+        backend.0.run(&spec("work", &["sh", "-c", "exec sleep 30"])).await.unwrap();
+        assert_eq!(fs::read_to_string(backend.0.state_dir.join("work.log.1")).unwrap(), "first\n");
+        assert_eq!(backend.0.names(), ["work"]);
+        backend.0.kill("work", "SIGKILL").await.unwrap();
+    }
+}
@@ -0,0 +1,41 @@
+// Network isolation for a profile container, selected with `--network`
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkPolicy {
+    // Docker's default bridge network
+    Default,
+    // No network access at all
+    None,
+    // Share the host's network stack
+    Host,
+    // Join a user-defined Docker network
+    Named(String),
+    // Route all traffic through another container's network namespace,
+    // e.g. a VPN or proxy container
+    Proxy(String),
+}
+
+impl NetworkPolicy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "" => Err("Empty --network value".to_string()),
+            "default" | "bridge" => Ok(NetworkPolicy::Default),
+            "none" => Ok(NetworkPolicy::None),
+            "host" => Ok(NetworkPolicy::Host),
+            _ => match value.strip_prefix("proxy:") {
+                Some("") => Err("--network proxy: needs a container name".to_string()),
+                Some(container) => Ok(NetworkPolicy::Proxy(container.to_string())),
+                None => Ok(NetworkPolicy::Named(value.to_string())),
+            },
+        }
+    }
+
+    pub fn to_docker_args(&self) -> Vec<String> {
+        match self {
+            NetworkPolicy::Default => Vec::new(),
+            NetworkPolicy::None => vec!["--network=none".to_string()],
+            NetworkPolicy::Host => vec!["--network=host".to_string()],
+            NetworkPolicy::Named(name) => vec![format!("--network={}", name)],
+            NetworkPolicy::Proxy(container) => vec![format!("--network=container:{}", container)],
+        }
+    }
+}
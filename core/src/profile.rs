@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::Path;
+
+use tokio::process::Command;
+
+// Files Firefox leaves behind while a profile is open; copying them into a new
+// profile would make Firefox think it is already in use
+const PROFILE_LOCK_FILES: [&str; 2] = ["lock", ".parentlock"];
+
+// Create a profile directory, optionally as a copy of an existing profile
+pub async fn create_profile(profile: &str, template: Option<&str>) -> Result<(), String> {
+    if Path::new(profile).exists() {
+        return Err(format!("Profile {} already exists", profile));
+    }
+
+    match template {
+        Some(template) => {
+            if !Path::new(template).is_dir() {
+                return Err(format!("Template profile {} does not exist", template));
+            }
+
+            let status = Command::new("cp")
+                .arg("-a")
+                .arg(template)
+                .arg(profile)
+                .status()
+                .await
+                .map_err(|e| format!("Failed to execute cp: {}", e))?;
+
+            if !status.success() {
+                let _ = fs::remove_dir_all(profile);
+                return Err(format!("Failed to copy {} to {}", template, profile));
+            }
+
+            for lock in PROFILE_LOCK_FILES {
+                let lock_path = Path::new(profile).join(lock);
+                if lock_path.symlink_metadata().is_ok() {
+                    fs::remove_file(&lock_path)
+                        .map_err(|e| format!("Failed to remove {}: {}", lock_path.display(), e))?;
+                }
+            }
+        }
+        None => {
+            fs::create_dir_all(profile).map_err(|e| format!("Failed to create {}: {}", profile, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn delete_profile(profile: &str) -> Result<(), String> {
+    if !Path::new(profile).is_dir() {
+        return Err(format!("Profile {} does not exist", profile));
+    }
+
+    fs::remove_dir_all(profile).map_err(|e| format!("Failed to delete {}: {}", profile, e))
+}
@@ -0,0 +1,221 @@
+use std::process::Stdio;
+
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
+
+use crate::cli::flag_value;
+use crate::engine::{DockerApi, EngineEvent, EventStream};
+use crate::native::{NativeBackend, NativeEvents};
+use crate::spec::ContainerSpec;
+
+// How container operations reach the container engine: by spawning the
+// docker CLI, or by talking to the daemon's API socket directly. The native
+// backend skips containers and runs the app as a host process.
+pub enum Runtime {
+    Cli,
+    Api(DockerApi),
+    Native(NativeBackend),
+}
+
+impl Runtime {
+    // `--backend api` (or MENU_RUNNER_BACKEND=api) selects the API socket,
+    // optionally given with `--docker-socket`, and `--backend native` runs
+    // apps without Docker
+    pub fn select(backend: Option<&str>, socket: Option<&str>) -> Result<Self, String> {
+        let backend = backend
+            .map(str::to_string)
+            .or_else(|| std::env::var("MENU_RUNNER_BACKEND").ok())
+            .unwrap_or_else(|| "cli".to_string());
+
+        match backend.as_str() {
+            "cli" => Ok(Runtime::Cli),
+            "api" => Ok(Runtime::Api(match socket {
+                Some(socket) => DockerApi::new(socket),
+                None => DockerApi::detect(),
+            })),
+            "native" => Ok(Runtime::Native(NativeBackend::detect())),
+            _ => Err(format!("Unknown backend: {} (expected cli, api or native)", backend)),
+        }
+    }
+
+    pub fn from_flags(flags: &[String]) -> Result<Self, String> {
+        Runtime::select(flag_value(flags, "--backend"), flag_value(flags, "--docker-socket"))
+    }
+
+    pub async fn run(&self, spec: &ContainerSpec) -> Result<(), String> {
+        match self {
+            Runtime::Cli => {
+                let mut args = vec!["run".to_string()];
+                args.extend(spec.to_run_args());
+                docker(&args).await
+            }
+            Runtime::Api(api) => {
+                api.create_container(spec).await?;
+                // Like `docker run`, leave nothing behind when it cannot start
+                if let Err(e) = api.start_container(&spec.name).await {
+                    if let Err(remove_error) = api.remove_container(&spec.name).await {
+                        return Err(format!("{} (and removing the container failed: {})", e, remove_error));
+                    }
+                    return Err(e);
+                }
+                Ok(())
+            }
+            Runtime::Native(native) => native.run(spec).await,
+        }
+    }
+
+    pub async fn pause(&self, name: &str) -> Result<(), String> {
+        match self {
+            Runtime::Cli => docker(&["pause", name]).await,
+            Runtime::Api(api) => api.pause_container(name).await,
+            Runtime::Native(native) => native.pause(name).await,
+        }
+    }
+
+    pub async fn unpause(&self, name: &str) -> Result<(), String> {
+        match self {
+            Runtime::Cli => docker(&["unpause", name]).await,
+            Runtime::Api(api) => api.unpause_container(name).await,
+            Runtime::Native(native) => native.unpause(name).await,
+        }
+    }
+
+    pub async fn kill(&self, name: &str, signal: &str) -> Result<(), String> {
+        match self {
+            Runtime::Cli => docker(&["kill", "--signal", signal, name]).await,
+            Runtime::Api(api) => api.kill_container(name, signal).await,
+            Runtime::Native(native) => native.kill(name, signal).await,
+        }
+    }
+
+    pub async fn remove(&self, name: &str) -> Result<(), String> {
+        match self {
+            Runtime::Cli => docker(&["rm", name]).await,
+            Runtime::Api(api) => api.remove_container(name).await,
+            Runtime::Native(native) => native.remove(name).await,
+        }
+    }
+
+    // Docker's status for the container (running, paused, exited, ...), or
+    // None when no such container exists
+    pub async fn state(&self, name: &str) -> Result<Option<String>, String> {
+        match self {
+            Runtime::Cli => {
+                let output = Command::new("docker")
+                    .args(["inspect", "--format", "{{.State.Status}}", name])
+                    .output()
+                    .await
+                    .map_err(|e| format!("Failed to execute Docker command: {}", e))?;
+
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    if stderr.to_lowercase().contains("no such object") {
+                        return Ok(None);
+                    }
+                    return Err(format!("docker inspect failed: {}", stderr.trim()));
+                }
+
+                Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_string()))
+            }
+            Runtime::Api(api) => Ok(api
+                .inspect_container(name)
+                .await?
+                .and_then(|info| info["State"]["Status"].as_str().map(str::to_string))),
+            Runtime::Native(native) => native.state(name).await,
+        }
+    }
+
+    // Human readable status report for `status`
+    pub async fn describe(&self, name: &str) -> Result<String, String> {
+        match self {
+            Runtime::Cli => {
+                let output = Command::new("docker")
+                    .args(["ps", "-a", "--filter", &format!("name={}", name)])
+                    .output()
+                    .await
+                    .map_err(|e| format!("Failed to execute Docker command: {}", e))?;
+
+                Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            }
+            Runtime::Api(api) => match api.inspect_container(name).await? {
+                None => Ok(format!("{}: not running", name)),
+                Some(info) => {
+                    let state = &info["State"];
+                    Ok(format!(
+                        "{}: {} (image {}, pid {}, started {})",
+                        name,
+                        state["Status"].as_str().unwrap_or("unknown"),
+                        info["Config"]["Image"].as_str().unwrap_or("unknown"),
+                        state["Pid"],
+                        state["StartedAt"].as_str().unwrap_or("unknown"),
+                    ))
+                }
+            },
+            Runtime::Native(native) => native.describe(name).await,
+        }
+    }
+
+    pub async fn events(&self) -> Result<Events, String> {
+        match self {
+            Runtime::Cli => {
+                let mut child = Command::new("docker")
+                    .args([
+                        "events",
+                        "--filter",
+                        "type=container",
+                        "--format",
+                        "{{.Actor.Attributes.name}}\t{{.Action}}",
+                    ])
+                    .stdout(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| format!("Failed to execute Docker command: {}", e))?;
+
+                let stdout = child.stdout.take().ok_or("Failed to read docker events output")?;
+                Ok(Events::Cli(child, BufReader::new(stdout).lines()))
+            }
+            Runtime::Api(api) => Ok(Events::Api(api.events().await?)),
+            Runtime::Native(native) => Ok(Events::Native(native.events().await?)),
+        }
+    }
+}
+
+// A stream of container events from either backend
+pub enum Events {
+    Cli(Child, Lines<BufReader<ChildStdout>>),
+    Api(EventStream),
+    Native(NativeEvents),
+}
+
+impl Events {
+    pub async fn next_event(&mut self) -> Result<Option<EngineEvent>, String> {
+        match self {
+            Events::Cli(_child, lines) => {
+                while let Some(line) = lines.next_line().await.map_err(|e| e.to_string())? {
+                    // Newer daemons report e.g. "exec_start: sh" for exec events; keep the verb
+                    if let Some((container, action)) = line.split_once('\t') {
+                        let action = action.split(':').next().unwrap_or(action).trim().to_string();
+                        return Ok(Some(EngineEvent { action, container: container.to_string() }));
+                    }
+                }
+                Ok(None)
+            }
+            Events::Api(stream) => stream.next_event().await,
+            Events::Native(events) => events.next_event().await,
+        }
+    }
+}
+
+async fn docker<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> Result<(), String> {
+    let status = Command::new("docker")
+        .args(args)
+        .status()
+        .await
+        .map_err(|e| format!("Failed to execute Docker command: {}", e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("docker exited with {}", status))
+    }
+}
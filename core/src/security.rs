@@ -0,0 +1,107 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+// Where /etc/machine-id comes from inside a profile container
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MachineId {
+    // Bind-mount the host's /etc/machine-id
+    Host,
+    // A random id generated once per profile, so profiles cannot be linked
+    // to each other or to the host through it
+    #[default]
+    Synthetic,
+    // No machine-id at all
+    None,
+}
+
+// Security settings applied to a profile container. The defaults are the
+// hardened ones; an app definition has to opt out of them explicitly.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecurityOptions {
+    pub cap_drop: Vec<String>,
+    pub cap_add: Vec<String>,
+    pub read_only: bool,
+    // Writable tmpfs mounts as path[:options], needed with a read-only root
+    pub tmpfs: Vec<String>,
+    // Path to a seccomp profile; Docker's default profile when unset
+    pub seccomp: Option<String>,
+    pub no_new_privileges: bool,
+    pub machine_id: MachineId,
+}
+
+impl Default for SecurityOptions {
+    fn default() -> Self {
+        SecurityOptions {
+            cap_drop: vec!["ALL".to_string()],
+            cap_add: Vec::new(),
+            read_only: true,
+            tmpfs: vec!["/tmp".to_string(), "/home/firefoxuser".to_string()],
+            seccomp: None,
+            no_new_privileges: true,
+            machine_id: MachineId::Synthetic,
+        }
+    }
+}
+
+impl SecurityOptions {
+    // Arguments for `docker run`
+    pub fn to_docker_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        for cap in &self.cap_drop {
+            args.push(format!("--cap-drop={}", cap));
+        }
+        for cap in &self.cap_add {
+            args.push(format!("--cap-add={}", cap));
+        }
+        if self.read_only {
+            args.push("--read-only".to_string());
+        }
+        for mount in &self.tmpfs {
+            args.push("--tmpfs".to_string());
+            args.push(mount.clone());
+        }
+        if let Some(seccomp) = &self.seccomp {
+            args.push("--security-opt".to_string());
+            args.push(format!("seccomp={}", seccomp));
+        }
+        if self.no_new_privileges {
+            args.push("--security-opt".to_string());
+            args.push("no-new-privileges".to_string());
+        }
+
+        args
+    }
+}
+
+pub fn default_machine_id_root() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".local/share/Menu_Runner/machine-ids")
+}
+
+// The synthetic machine-id file for a container, created on first use and
+// kept afterwards so Firefox sees the same id on every start
+pub fn synthetic_machine_id(root: &Path, container_name: &str) -> Result<PathBuf, String> {
+    let path = root.join(container_name);
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    fs::create_dir_all(root).map_err(|e| format!("Failed to create {}: {}", root.display(), e))?;
+
+    let mut bytes = [0u8; 16];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut bytes))
+        .map_err(|e| format!("Failed to read /dev/urandom: {}", e))?;
+
+    // machine-id is 32 lowercase hex characters and a newline
+    let id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    fs::write(&path, format!("{}\n", id)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    Ok(path)
+}
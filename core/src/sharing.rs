@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::Path;
+
+use crate::cli::flag_value;
+
+// Where the downloads directory is mounted inside the Firefox container.
+// Firefox falls back to ~/Downloads when no XDG download dir is configured.
+pub const CONTAINER_DOWNLOADS_PATH: &str = "/home/firefoxuser/Downloads";
+
+// Lines in the profile's user.js written by Menu_Runner_system
+const USER_JS_BEGIN: &str = "// Menu_Runner clipboard policy begin";
+const USER_JS_END: &str = "// Menu_Runner clipboard policy end";
+
+// A host directory shared with the container as its Downloads folder
+#[derive(Debug, Clone, PartialEq)]
+pub struct Downloads {
+    pub dir: String,
+    // Give each profile its own subdirectory of `dir`
+    pub per_profile: bool,
+}
+
+impl Downloads {
+    pub fn from_flags(flags: &[String]) -> Option<Self> {
+        flag_value(flags, "--downloads").map(|dir| Downloads {
+            dir: dir.trim_end_matches('/').to_string(),
+            per_profile: flags.iter().any(|flag| flag == "--downloads-per-profile"),
+        })
+    }
+
+    // The host directory to mount for a profile, created if missing
+    pub fn host_dir(&self, profile: &str) -> Result<String, String> {
+        let dir = match (self.per_profile, Path::new(profile).file_name()) {
+            (true, Some(name)) => Path::new(&self.dir).join(name),
+            _ => Path::new(&self.dir).to_path_buf(),
+        };
+
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        Ok(dir.to_string_lossy().to_string())
+    }
+}
+
+// How much of the clipboard a profile shares with the rest of the desktop.
+// Every container talks to the host X server, so the X11 selections are
+// reachable either way; `isolated` turns off what Firefox itself puts into
+// or takes from them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipboardPolicy {
+    // Firefox's defaults: selecting text fills PRIMARY, middle-click pastes
+    // and pages may use the clipboard events
+    Shared,
+    // No automatic PRIMARY copy, no middle-click paste and no page access
+    // to clipboard events; explicit copy and paste still work
+    Isolated,
+}
+
+impl ClipboardPolicy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "shared" => Ok(ClipboardPolicy::Shared),
+            "isolated" => Ok(ClipboardPolicy::Isolated),
+            _ => Err(format!("Unknown clipboard policy: {} (expected shared or isolated)", value)),
+        }
+    }
+
+    fn prefs(&self) -> [(&'static str, bool); 3] {
+        let shared = *self == ClipboardPolicy::Shared;
+        [
+            ("clipboard.autocopy", shared),
+            ("middlemouse.paste", shared),
+            ("dom.event.clipboardevents.enabled", shared),
+        ]
+    }
+
+    // Write the policy into the profile's user.js, replacing an earlier one.
+    // Both policies write explicit values because Firefox keeps prefs set by
+    // user.js in prefs.js after the lines are gone.
+    pub fn apply(&self, profile: &str) -> Result<(), String> {
+        let path = Path::new(profile).join("user.js");
+        let existing = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut in_block = false;
+        for line in existing.lines() {
+            match line {
+                USER_JS_BEGIN => in_block = true,
+                USER_JS_END => in_block = false,
+                _ if !in_block => lines.push(line.to_string()),
+                _ => {}
+            }
+        }
+
+        lines.push(USER_JS_BEGIN.to_string());
+        for (pref, value) in self.prefs() {
+            lines.push(format!("user_pref(\"{}\", {});", pref, value));
+        }
+        lines.push(USER_JS_END.to_string());
+
+        fs::write(&path, lines.join("\n") + "\n").map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
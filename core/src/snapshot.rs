@@ -0,0 +1,183 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::process::Command;
+
+// Snapshots are named after the UTC time they were taken, e.g. 20261018T153000Z,
+// so sorting the ids sorts them by age
+const SNAPSHOT_EXTENSION: &str = ".tar.gz";
+
+// How many snapshots to keep per profile and for how long
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub keep: usize,
+    pub max_age_days: Option<u64>,
+}
+
+pub struct Snapshot {
+    pub id: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+pub fn default_snapshot_root() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".local/share/Menu_Runner/snapshots")
+}
+
+// Archive the profile directory into <root>/<container_name>/<id>.tar.gz
+pub async fn create_snapshot(root: &Path, container_name: &str, profile: &str) -> Result<Snapshot, String> {
+    let dir = root.join(container_name);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let id = format_timestamp(SystemTime::now());
+    let path = dir.join(format!("{}{}", id, SNAPSHOT_EXTENSION));
+    if path.exists() {
+        return Err(format!("Snapshot {} already exists", id));
+    }
+
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(&path)
+        .arg("-C")
+        .arg(profile)
+        .arg(".")
+        .status()
+        .await
+        .map_err(|e| format!("Failed to execute tar: {}", e))?;
+
+    if !status.success() {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Failed to archive {}", profile));
+    }
+
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    Ok(Snapshot { id, path, size })
+}
+
+// Snapshots of a container's profile, oldest first
+pub fn list_snapshots(root: &Path, container_name: &str) -> Result<Vec<Snapshot>, String> {
+    let dir = root.join(container_name);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries = fs::read_dir(&dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut snapshots: Vec<Snapshot> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let id = name.strip_suffix(SNAPSHOT_EXTENSION)?.to_string();
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            Some(Snapshot { id, path: entry.path(), size })
+        })
+        .collect();
+
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(snapshots)
+}
+
+// Replace the profile directory with the contents of a snapshot. The previous
+// contents are kept next to the profile as <profile>.pre-restore until the
+// next restore.
+pub async fn restore_snapshot(root: &Path, container_name: &str, profile: &str, id: Option<&str>) -> Result<String, String> {
+    let snapshots = list_snapshots(root, container_name)?;
+    let snapshot = match id {
+        Some(id) => snapshots
+            .iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| format!("No snapshot {} for {}", id, container_name))?,
+        None => snapshots
+            .last()
+            .ok_or_else(|| format!("No snapshots for {}", container_name))?,
+    };
+
+    let staging = PathBuf::from(format!("{}.restore-tmp", profile));
+    let previous = PathBuf::from(format!("{}.pre-restore", profile));
+
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| format!("Failed to clear {}: {}", staging.display(), e))?;
+    }
+    fs::create_dir_all(&staging).map_err(|e| format!("Failed to create {}: {}", staging.display(), e))?;
+
+    let status = Command::new("tar")
+        .arg("-xzf")
+        .arg(&snapshot.path)
+        .arg("-C")
+        .arg(&staging)
+        .status()
+        .await
+        .map_err(|e| format!("Failed to execute tar: {}", e))?;
+
+    if !status.success() {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(format!("Failed to extract snapshot {}", snapshot.id));
+    }
+
+    if previous.exists() {
+        fs::remove_dir_all(&previous).map_err(|e| format!("Failed to clear {}: {}", previous.display(), e))?;
+    }
+    if Path::new(profile).exists() {
+        fs::rename(profile, &previous).map_err(|e| format!("Failed to move {} aside: {}", profile, e))?;
+    }
+    fs::rename(&staging, profile).map_err(|e| format!("Failed to restore {}: {}", profile, e))?;
+
+    Ok(snapshot.id.clone())
+}
+
+// Delete snapshots beyond the newest `keep` and any older than `max_age_days`
+pub fn prune_snapshots(root: &Path, container_name: &str, policy: &RetentionPolicy) -> Result<Vec<String>, String> {
+    let snapshots = list_snapshots(root, container_name)?;
+    let cutoff = policy
+        .max_age_days
+        .map(|days| format_timestamp(SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60)));
+
+    let mut removed = Vec::new();
+    let excess = snapshots.len().saturating_sub(policy.keep);
+
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let too_many = index < excess;
+        let too_old = cutoff.as_ref().is_some_and(|cutoff| snapshot.id < *cutoff);
+
+        if too_many || too_old {
+            fs::remove_file(&snapshot.path)
+                .map_err(|e| format!("Failed to remove {}: {}", snapshot.path.display(), e))?;
+            removed.push(snapshot.id.clone());
+        }
+    }
+
+    Ok(removed)
+}
+
+// Format a time as a compact UTC timestamp, e.g. 20261018T153000Z
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// Days since 1970-01-01 to a (year, month, day) date in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
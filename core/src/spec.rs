@@ -0,0 +1,52 @@
+use crate::limits::ResourceLimits;
+use crate::network::NetworkPolicy;
+use crate::security::SecurityOptions;
+
+// Everything needed to launch a profile container, independent of whether it
+// is started through the docker CLI or the Engine API
+#[derive(Debug, Clone)]
+pub struct ContainerSpec {
+    pub name: String,
+    pub image: String,
+    pub command: Vec<String>,
+    pub env: Vec<String>,
+    // host:container[:options] bind mounts
+    pub binds: Vec<String>,
+    pub user: String,
+    pub auto_remove: bool,
+    pub limits: ResourceLimits,
+    pub network: NetworkPolicy,
+    pub security: SecurityOptions,
+}
+
+impl ContainerSpec {
+    // Arguments for `docker run`, without the leading "run"
+    pub fn to_run_args(&self) -> Vec<String> {
+        let mut args = vec!["-d".to_string()];
+
+        if self.auto_remove {
+            args.push("--rm".to_string());
+        }
+        args.push("--name".to_string());
+        args.push(self.name.clone());
+
+        for var in &self.env {
+            args.push("-e".to_string());
+            args.push(var.clone());
+        }
+        for bind in &self.binds {
+            args.push("-v".to_string());
+            args.push(bind.clone());
+        }
+
+        args.push("--user".to_string());
+        args.push(self.user.clone());
+        args.extend(self.security.to_docker_args());
+        args.extend(self.limits.to_docker_args());
+        args.extend(self.network.to_docker_args());
+
+        args.push(self.image.clone());
+        args.extend(self.command.iter().cloned());
+        args
+    }
+}
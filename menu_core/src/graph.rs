@@ -0,0 +1,348 @@
+// menu_core/src/graph.rs
+// State machine diagrams of the menu items, as Graphviz DOT or Mermaid
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::models::{Guard, MenuConfig, MenuItemConfig, StateMachine, Transition};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            other => Err(format!("Unknown graph format: {} (expected dot or mermaid)", other)),
+        }
+    }
+}
+
+const HIGHLIGHT: &str = "#FF8C00";
+
+// The state an item, or one of its regions, is known to be in
+pub type CurrentState<'a> = &'a dyn Fn(&str, Option<&str>) -> Option<String>;
+
+// One diagram of every item's state machine, or only of the item labelled
+// `only`. The state `current` gives for an item label and region, if any,
+// is highlighted.
+pub fn render_graph(
+    config: &MenuConfig,
+    format: GraphFormat,
+    only: Option<&str>,
+    current: CurrentState,
+) -> Result<String, String> {
+    let items: Vec<&MenuItemConfig> = config.menu_items.iter()
+        .filter(|item| only.map_or(true, |label| item.label == label))
+        .collect();
+    if let (Some(label), true) = (only, items.is_empty()) {
+        return Err(format!("No menu item named {}", label));
+    }
+
+    let mut out = String::new();
+    match format {
+        GraphFormat::Dot => {
+            out.push_str("digraph menu {\n");
+            out.push_str("    compound=true;\n");
+            out.push_str("    node [shape=box, style=\"rounded,filled\"];\n");
+            for (index, item) in items.iter().enumerate() {
+                dot_item(&mut out, index, item, current);
+            }
+            out.push_str("}\n");
+        }
+        GraphFormat::Mermaid => {
+            out.push_str("stateDiagram-v2\n");
+            let _ = writeln!(out, "    classDef current stroke:{},stroke-width:3px", HIGHLIGHT);
+            for (index, item) in items.iter().enumerate() {
+                mermaid_item(&mut out, index, item, current);
+            }
+        }
+    }
+    Ok(out)
+}
+
+// The edge label of a transition: its action, the guards it waits for and
+// the timer that takes it
+fn edge_label(action: &str, transition: &Transition, machine: &StateMachine, from: &str) -> String {
+    let mut label = action.to_string();
+    if !transition.guards.is_empty() {
+        let guards: Vec<String> = transition.guards.iter().map(describe_guard).collect();
+        let _ = write!(label, " [{}]", guards.join(", "));
+    }
+    if let Some(after) = machine.states.get(from).and_then(|state| state.after.as_ref()) {
+        if after.action == action {
+            let _ = write!(label, " after {}", after.duration);
+        }
+    }
+    label
+}
+
+fn describe_guard(guard: &Guard) -> String {
+    match guard {
+        Guard::FileExists { file_exists } => format!("file {}", file_exists),
+        Guard::Env { env } => format!("env {}", env),
+        Guard::Probe { probe } => format!("probe {}", probe),
+        Guard::ItemState { item, state } => format!("{} is {}", item, state),
+    }
+}
+
+// States directly nested in `parent`, or the top-level states for None
+fn children<'a>(machine: &'a StateMachine, parent: Option<&str>) -> Vec<&'a String> {
+    machine.states.iter()
+        .filter(|(_, state)| state.parent.as_deref() == parent)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+fn is_composite(machine: &StateMachine, name: &str) -> bool {
+    machine.states.values().any(|state| state.parent.as_deref() == Some(name))
+}
+
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn dot_item(out: &mut String, index: usize, item: &MenuItemConfig, current: CurrentState) {
+    let _ = writeln!(out, "    subgraph cluster_{} {{", index);
+    let _ = writeln!(out, "        label={};", dot_quote(&item.label));
+    let state = current(&item.label, None);
+    dot_machine(out, &format!("{}", index), &item.state_machine, state.as_deref(), 2);
+    for (name, region) in &item.state_machine.regions {
+        let prefix = format!("{}.{}", index, name);
+        let state = current(&item.label, Some(name));
+        let mut label = format!("{}: {}", item.label, name);
+        if let Some(within) = &region.within {
+            let _ = write!(label, " (within {})", within);
+        }
+        let _ = writeln!(out, "        subgraph {} {{", dot_quote(&format!("cluster_{}", prefix)));
+        let _ = writeln!(out, "            label={};", dot_quote(&label));
+        let _ = writeln!(out, "            style=dashed;");
+        dot_machine(out, &prefix, &region.machine, state.as_deref(), 3);
+        out.push_str("        }\n");
+    }
+    out.push_str("    }\n");
+}
+
+// Nodes, clusters for nested states and edges of one machine. Node ids are
+// `prefix/state` so items and regions never share one.
+fn dot_machine(out: &mut String, prefix: &str, machine: &StateMachine, current: Option<&str>, depth: usize) {
+    let node = |state: &str| dot_quote(&format!("{}/{}", prefix, state));
+    let cluster = |state: &str| dot_quote(&format!("cluster_{}/{}", prefix, state));
+
+    fn states(out: &mut String, prefix: &str, machine: &StateMachine, parent: Option<&str>, current: Option<&str>, depth: usize) {
+        let indent = "    ".repeat(depth);
+        for name in children(machine, parent) {
+            let state = &machine.states[name];
+            if is_composite(machine, name) {
+                let _ = writeln!(out, "{}subgraph {} {{", indent, dot_quote(&format!("cluster_{}/{}", prefix, name)));
+                let _ = writeln!(out, "{}    label={};", indent, dot_quote(name));
+                if current.is_some_and(|current| machine.is_in(current, name)) {
+                    let _ = writeln!(out, "{}    color={}; penwidth=3;", indent, dot_quote(HIGHLIGHT));
+                }
+                states(out, prefix, machine, Some(name), current, depth + 1);
+                let _ = writeln!(out, "{}}}", indent);
+                continue;
+            }
+
+            let mut attributes = vec![format!("label={}", dot_quote(name))];
+            if let Some(bg) = state.display.get("bg") {
+                attributes.push(format!("fillcolor={}", dot_quote(bg)));
+            }
+            if let Some(fg) = state.display.get("fg") {
+                attributes.push(format!("fontcolor={}", dot_quote(fg)));
+            }
+            if current == Some(name.as_str()) {
+                attributes.push(format!("color={}, penwidth=3", dot_quote(HIGHLIGHT)));
+            }
+            let _ = writeln!(
+                out, "{}{} [{}];",
+                indent, dot_quote(&format!("{}/{}", prefix, name)), attributes.join(", ")
+            );
+        }
+    }
+    states(out, prefix, machine, None, current, depth);
+
+    // Composite states have no node of their own: their edges start or end
+    // at the state they resolve to, clipped at the cluster border
+    let indent = "    ".repeat(depth);
+    let _ = writeln!(out, "{}{} [shape=point, label=\"\"];", indent, node("[*]"));
+    let _ = writeln!(out, "{}{} -> {};", indent, node("[*]"), node(&machine.initial()));
+    for (from, state) in &machine.states {
+        for (action, transition) in &state.transitions {
+            let tail = machine.resolve(from);
+            let head = machine.resolve(&transition.to);
+            let mut attributes = vec![format!("label={}", dot_quote(&edge_label(action, transition, machine, from)))];
+            if is_composite(machine, from) && !machine.is_in(&transition.to, from) {
+                attributes.push(format!("ltail={}", cluster(from)));
+            }
+            if is_composite(machine, &transition.to) && !machine.is_in(from, &transition.to) {
+                attributes.push(format!("lhead={}", cluster(&transition.to)));
+            }
+            let _ = writeln!(out, "{}{} -> {} [{}];", indent, node(&tail), node(&head), attributes.join(", "));
+        }
+    }
+}
+
+fn mermaid_item(out: &mut String, index: usize, item: &MenuItemConfig, current: CurrentState) {
+    let _ = writeln!(out, "    state \"{}\" as item{} {{", mermaid_text(&item.label), index);
+    let prefix = format!("s{}", index);
+    let state = current(&item.label, None);
+    mermaid_machine(out, &prefix, &item.state_machine, state.as_deref(), 2);
+    for (region_index, (name, region)) in item.state_machine.regions.iter().enumerate() {
+        // Regions are concurrent parts of the item's composite state
+        out.push_str("        --\n");
+        let mut label = name.clone();
+        if let Some(within) = &region.within {
+            let _ = write!(label, " (within {})", within);
+        }
+        let _ = writeln!(out, "        state \"{}\" as {}r{} {{", mermaid_text(&label), prefix, region_index);
+        let state = current(&item.label, Some(name));
+        mermaid_machine(out, &format!("{}r{}", prefix, region_index), &region.machine, state.as_deref(), 3);
+        out.push_str("        }\n");
+    }
+    out.push_str("    }\n");
+}
+
+// Mermaid ids are plain identifiers, so states are numbered in order and
+// declared with their name as the description
+fn mermaid_machine(out: &mut String, prefix: &str, machine: &StateMachine, current: Option<&str>, depth: usize) {
+    let ids: HashMap<&String, String> = machine.states.keys()
+        .enumerate()
+        .map(|(n, name)| (name, format!("{}_{}", prefix, n)))
+        .collect();
+
+    fn states(out: &mut String, machine: &StateMachine, ids: &HashMap<&String, String>, parent: Option<&str>, current: Option<&str>, depth: usize) {
+        let indent = "    ".repeat(depth);
+        for name in children(machine, parent) {
+            let id = &ids[name];
+            if is_composite(machine, name) {
+                let _ = writeln!(out, "{}state \"{}\" as {} {{", indent, mermaid_text(name), id);
+                if let Some(initial) = machine.states[name].initial.as_ref().and_then(|initial| ids.get(initial)) {
+                    let _ = writeln!(out, "{}    [*] --> {}", indent, initial);
+                }
+                states(out, machine, ids, Some(name), current, depth + 1);
+                let _ = writeln!(out, "{}}}", indent);
+            } else {
+                let _ = writeln!(out, "{}state \"{}\" as {}", indent, mermaid_text(name), id);
+            }
+            if current == Some(name.as_str()) {
+                let _ = writeln!(out, "{}class {} current", indent, id);
+            }
+        }
+    }
+    states(out, machine, &ids, None, current, depth);
+
+    let indent = "    ".repeat(depth);
+    if let Some(initial) = ids.get(&machine.initial_state) {
+        let _ = writeln!(out, "{}[*] --> {}", indent, initial);
+    }
+    for (from, state) in &machine.states {
+        for (action, transition) in &state.transitions {
+            let Some(to) = ids.get(&transition.to) else { continue };
+            let label = mermaid_text(&edge_label(action, transition, machine, from));
+            let _ = writeln!(out, "{}{} --> {} : {}", indent, ids[from], to, label);
+        }
+    }
+}
+
+// Mermaid has no escaping for these in labels
+fn mermaid_text(text: &str) -> String {
+    text.replace('"', "'").replace(':', " ").replace(['{', '}'], "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "menu_items:
+- label: vpn
+  command_template: echo
+- label: work
+  command_template: echo
+  state_machine:
+    initial_state: down
+    states:
+      down:
+        transitions:
+          start:
+            to: up
+            guards:
+            - item: vpn
+              state: started
+      up:
+        initial: running
+        after:
+          duration: 2h
+          action: kill
+        transitions:
+          kill: down
+      running:
+        parent: up
+        transitions:
+          freeze: frozen
+      frozen:
+        parent: up
+        transitions:
+          unfreeze: running
+";
+
+    fn render(format: GraphFormat, only: Option<&str>, current: CurrentState) -> String {
+        let config: MenuConfig = serde_yaml::from_str(CONFIG).unwrap();
+        render_graph(&config, format, only, current).unwrap()
+    }
+
+    fn nothing(_: &str, _: Option<&str>) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn dot_nests_composite_states_and_labels_edges() {
+        let graph = render(GraphFormat::Dot, Some("work"), &nothing);
+
+        assert!(!graph.contains("\"vpn\""), "{}", graph);
+        assert!(graph.contains("subgraph \"cluster_0/up\""), "{}", graph);
+        assert!(graph.contains("\"0/[*]\" -> \"0/down\";"), "{}", graph);
+        // edges into and out of up end at the state it resolves to
+        assert!(graph.contains("\"0/down\" -> \"0/running\" [label=\"start [vpn is started]\", lhead=\"cluster_0/up\"];"), "{}", graph);
+        assert!(graph.contains("\"0/running\" -> \"0/down\" [label=\"kill after 2h\", ltail=\"cluster_0/up\"];"), "{}", graph);
+        assert!(!graph.contains("penwidth"), "{}", graph);
+    }
+
+    #[test]
+    fn current_state_and_its_parents_are_highlighted() {
+        let current = |label: &str, region: Option<&str>| {
+            (label == "work" && region.is_none()).then(|| "frozen".to_string())
+        };
+
+        let dot = render(GraphFormat::Dot, None, &current);
+        assert!(dot.contains("\"1/frozen\" [label=\"frozen\", color=\"#FF8C00\", penwidth=3];"), "{}", dot);
+        assert!(dot.contains("label=\"up\";\n            color=\"#FF8C00\"; penwidth=3;"), "{}", dot);
+        assert_eq!(dot.matches("penwidth=3").count(), 2, "{}", dot);
+
+        let mermaid = render(GraphFormat::Mermaid, None, &current);
+        assert!(mermaid.contains("state \"frozen\" as s1_1\n            class s1_1 current"), "{}", mermaid);
+    }
+
+    #[test]
+    fn mermaid_declares_states_by_number() {
+        let graph = render(GraphFormat::Mermaid, Some("work"), &nothing);
+
+        assert!(graph.starts_with("stateDiagram-v2\n"), "{}", graph);
+        assert!(graph.contains("state \"work\" as item0 {"), "{}", graph);
+        assert!(graph.contains("state \"up\" as s0_3 {\n            [*] --> s0_2"), "{}", graph);
+        assert!(graph.contains("s0_0 --> s0_3 : start [vpn is started]"), "{}", graph);
+    }
+
+    #[test]
+    fn unknown_items_and_formats_are_errors() {
+        let config: MenuConfig = serde_yaml::from_str(CONFIG).unwrap();
+        assert_eq!(render_graph(&config, GraphFormat::Dot, Some("mail"), &nothing), Err("No menu item named mail".to_string()));
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+}
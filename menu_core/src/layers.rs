@@ -0,0 +1,206 @@
+// menu_core/src/layers.rs
+// The menu config spread over several files: each file's includes, and the
+// system, user, project and local layers merged in that order
+use std::path::{Path, PathBuf};
+
+use crate::models::MenuConfig;
+use crate::parser_async::MenuError;
+
+pub const PROJECT_CONFIG: &str = "configs/menu_config_color.yaml";
+// Personal overrides next to the project config, kept out of version control
+pub const LOCAL_CONFIG: &str = "configs/menu_config_color.local.yaml";
+
+// One place a config may be read from
+pub struct Layer {
+    pub name: &'static str,
+    pub path: PathBuf,
+}
+
+// Every layer, lowest first; later layers override earlier ones
+pub fn config_layers() -> Vec<Layer> {
+    let mut layers = vec![Layer { name: "system", path: PathBuf::from("/etc/menu_runner/menu_config.yaml") }];
+
+    let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = user_dir {
+        layers.push(Layer { name: "user", path: dir.join("menu_runner/menu_config.yaml") });
+    }
+
+    layers.push(Layer { name: "project", path: PathBuf::from(PROJECT_CONFIG) });
+    layers.push(Layer { name: "local", path: PathBuf::from(LOCAL_CONFIG) });
+    layers
+}
+
+// Merge every layer that exists. Returns the merged config and every file
+// read, in the order they were merged.
+pub fn load_layered_config() -> Result<(MenuConfig, Vec<PathBuf>), MenuError> {
+    let mut merged = MenuConfig::default();
+    let mut read = Vec::new();
+
+    let layers = config_layers();
+    for layer in &layers {
+        if layer.path.exists() {
+            merged.merge(read_with_includes(&layer.path, &mut Vec::new(), &mut read)?);
+        }
+    }
+
+    if read.is_empty() {
+        return Err(MenuError::FileNotFound(PROJECT_CONFIG.to_string()));
+    }
+    Ok((merged, read))
+}
+
+// A single config file without its includes, as it is written on disk
+pub fn read_config_file(path: &Path) -> Result<MenuConfig, MenuError> {
+    let content = std::fs::read_to_string(path)?;
+    serde_yaml::from_str(&content).map_err(|e| MenuError::ParseError(format!("{}: {}", path.display(), e)))
+}
+
+// A config file with what it includes merged underneath it. `stack` holds
+// the files including this one, to catch include cycles.
+fn read_with_includes(path: &Path, stack: &mut Vec<PathBuf>, read: &mut Vec<PathBuf>) -> Result<MenuConfig, MenuError> {
+    let canonical = path.canonicalize()?;
+    if stack.contains(&canonical) {
+        let cycle: Vec<String> = stack.iter()
+            .skip_while(|file| **file != canonical)
+            .chain(std::iter::once(&canonical))
+            .map(|file| file.display().to_string())
+            .collect();
+        return Err(MenuError::InvalidConfig(format!("Circular include: {}", cycle.join(" -> "))));
+    }
+
+    let mut config = read_config_file(path)?;
+    stack.push(canonical);
+
+    // What the file removes applies to earlier layers as well as its includes
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut merged = MenuConfig { remove: config.remove.clone(), ..MenuConfig::default() };
+    for pattern in std::mem::take(&mut config.include) {
+        for file in resolve_include(base_dir, &pattern)? {
+            merged.merge(read_with_includes(&file, stack, read)?);
+        }
+    }
+    merged.merge(config);
+    read.push(path.to_path_buf());
+
+    stack.pop();
+    Ok(merged)
+}
+
+// The files an include names: a plain path must exist, a glob pattern may
+// match nothing and its matches are read in sorted order
+fn resolve_include(base_dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, MenuError> {
+    let full = base_dir.join(pattern);
+
+    if !pattern.contains(['*', '?', '[']) {
+        if !full.exists() {
+            return Err(MenuError::FileNotFound(full.display().to_string()));
+        }
+        return Ok(vec![full]);
+    }
+
+    let matches = glob::glob(&full.to_string_lossy())
+        .map_err(|e| MenuError::InvalidConfig(format!("Invalid include pattern {}: {}", pattern, e)))?;
+    let mut files: Vec<PathBuf> = matches.filter_map(Result::ok).filter(|file| file.is_file()).collect();
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A directory of config files, removed afterwards
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("menu-runner-layers-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        fn write(&self, name: &str, content: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+            path
+        }
+
+        fn read(&self, name: &str) -> Result<MenuConfig, MenuError> {
+            read_with_includes(&self.0.join(name), &mut Vec::new(), &mut Vec::new())
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn item(label: &str, command: &str) -> String {
+        format!("- label: {}\n  command_template: {}\n", label, command)
+    }
+
+    fn items(config: &MenuConfig) -> Vec<(&str, &str)> {
+        config.menu_items.iter().map(|item| (item.label.as_str(), item.command_template.as_str())).collect()
+    }
+
+    #[test]
+    fn includes_are_merged_underneath_the_file() {
+        let dir = TestDir::new("includes");
+        dir.write("base.yaml", &format!("menu_items:\n{}{}", item("vpn", "vpn"), item("work", "old")));
+        dir.write("extra/b.yaml", &format!("menu_items:\n{}", item("chat", "chat-b")));
+        dir.write("extra/a.yaml", &format!("menu_items:\n{}", item("chat", "chat-a")));
+        dir.write("main.yaml", &format!("include: [base.yaml, \"extra/*.yaml\", \"none/*.yaml\"]\nmenu_items:\n{}", item("work", "new")));
+
+        let mut read = Vec::new();
+        let config = read_with_includes(&dir.0.join("main.yaml"), &mut Vec::new(), &mut read).unwrap();
+
+        // glob matches in sorted order, so b.yaml wins over a.yaml
+        assert_eq!(items(&config), [("vpn", "vpn"), ("work", "new"), ("chat", "chat-b")]);
+        let names: Vec<String> = read.iter().map(|file| file.strip_prefix(&dir.0).unwrap().display().to_string()).collect();
+        assert_eq!(names, ["base.yaml", "extra/a.yaml", "extra/b.yaml", "main.yaml"]);
+    }
+
+    #[test]
+    fn remove_drops_items_of_includes_and_earlier_layers() {
+        let dir = TestDir::new("remove");
+        dir.write("system.yaml", &format!("menu_items:\n{}{}", item("vpn", "vpn"), item("mail", "mail")));
+        dir.write("shared.yaml", &format!("menu_items:\n{}{}", item("chat", "chat"), item("work", "work")));
+        dir.write("user.yaml", &format!("include: [shared.yaml]\nremove: [mail, chat]\nmenu_items:\n{}", item("notes", "notes")));
+
+        let mut merged = MenuConfig::default();
+        merged.merge(dir.read("system.yaml").unwrap());
+        merged.merge(dir.read("user.yaml").unwrap());
+
+        assert_eq!(items(&merged), [("vpn", "vpn"), ("work", "work"), ("notes", "notes")]);
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = TestDir::new("cycle");
+        dir.write("a.yaml", "include: [b.yaml]\n");
+        dir.write("b.yaml", "include: [c.yaml]\n");
+        dir.write("c.yaml", "include: [b.yaml]\n");
+
+        let error = dir.read("a.yaml").unwrap_err().to_string();
+
+        let b = dir.0.join("b.yaml").canonicalize().unwrap();
+        let c = dir.0.join("c.yaml").canonicalize().unwrap();
+        let cycle = format!("Circular include: {} -> {} -> {}", b.display(), c.display(), b.display());
+        assert!(error.ends_with(&cycle), "{}", error);
+    }
+
+    #[test]
+    fn a_missing_plain_include_is_an_error() {
+        let dir = TestDir::new("missing");
+        dir.write("main.yaml", "include: [gone.yaml]\n");
+
+        assert!(matches!(dir.read("main.yaml"), Err(MenuError::FileNotFound(path)) if path.ends_with("gone.yaml")));
+    }
+}
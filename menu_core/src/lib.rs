@@ -1,12 +1,20 @@
 // menu_core/src/lib.rs
 // Export all public items from this crate
+pub mod graph;
+pub mod layers;
+pub mod migrate;
 pub mod models;
 pub mod parser_async;
+pub mod state_file;
+pub mod supervisor;
 
-pub use models::{CommandInfo, GroupedMenuEntry, SlintMenuEntry};
-pub use models::{ButtonManager, MenuConfig};
-pub use parser_async::{
-    load_menu_async, load_menu_json_async, load_menu_yaml_async,
-    load_menu_config_color, load_menu_with_button_manager,
-    create_slint_menu_entries, build_grouped_entries, MenuError
-};
\ No newline at end of file
+pub use graph::{render_graph, CurrentState, GraphFormat};
+pub use layers::{config_layers, load_layered_config, Layer};
+pub use migrate::migrate_config;
+pub use models::SlintMenuEntry;
+pub use models::{ActionCommand, After, ButtonManager, MenuConfig, MenuItemConfig, ResourceLimits, RestartPolicy, Runner, StateMachine};
+pub use models::{Guard, Hook, Region, Requirement, Selection, Session, SessionItem, SessionStep, SupervisorConfig, Transition};
+pub use models::parse_duration;
+pub use state_file::{load_states, save_states, state_file_path, SavedState, SavedStates};
+pub use supervisor::{ExitDecision, Supervisor};
+pub use parser_async::{load_menu_config_color, load_menu_with_button_manager, menu_entries, save_menu_item, MenuError};
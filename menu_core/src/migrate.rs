@@ -0,0 +1,333 @@
+// menu_core/src/migrate.rs
+// Conversion of older menu formats into MenuConfig with state machines
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::models::{MenuConfig, MenuItemConfig, State, StateMachine, Transition};
+
+// Every format menu configs were written in, tried in this order. Legacy
+// items need a `command`, which current items never have, while a legacy
+// file would pass for a current one with every item on the standard machine.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyConfig {
+    Legacy(LegacyMenu),
+    Current(MenuConfig),
+    Commands(Vec<CommandInfo>),
+}
+
+// One command of the flat menu_config.yaml and future_menu.json lists
+#[derive(Deserialize)]
+struct CommandInfo {
+    name: String,
+    command: String,
+    category: String,
+}
+
+// menu_config.json and future_menu_*.yaml: one command with an ACTION
+// placeholder and the actions it takes
+#[derive(Deserialize)]
+struct LegacyMenu {
+    menu_items: Vec<LegacyItem>,
+}
+
+#[derive(Deserialize)]
+struct LegacyItem {
+    label: String,
+    command: String,
+    // `start: {freeze_toggle: [freeze, unfreeze]}` or `kill: {}`
+    #[serde(default)]
+    actions: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+}
+
+// Read a menu config in any of the older formats, or the current one, from
+// JSON or YAML text
+pub fn migrate_config(content: &str) -> Result<MenuConfig, String> {
+    let parsed: AnyConfig = serde_yaml::from_str(content).map_err(|e| match e.location() {
+        Some(location) => format!("Not a menu config in any known format (line {})", location.line()),
+        None => "Not a menu config in any known format".to_string(),
+    })?;
+
+    let config = match parsed {
+        AnyConfig::Current(config) => config,
+        AnyConfig::Legacy(legacy) => MenuConfig {
+            menu_items: legacy.menu_items.into_iter().map(migrate_item).collect::<Result<_, _>>()?,
+            ..MenuConfig::default()
+        },
+        AnyConfig::Commands(commands) => MenuConfig {
+            menu_items: migrate_commands(&commands),
+            ..MenuConfig::default()
+        },
+    };
+
+    config.validate_state_machines()?;
+    config.validate_requirements()?;
+    config.validate_timers()?;
+    Ok(config)
+}
+
+fn migrate_item(legacy: LegacyItem) -> Result<MenuItemConfig, String> {
+    let mut actions: Vec<String> = legacy.actions.keys().cloned().collect();
+    let mut toggles = Vec::new();
+
+    for options in legacy.actions.values() {
+        for (name, pair) in options {
+            match (name.strip_suffix("_toggle"), pair.as_slice()) {
+                (Some(_), [on, off]) => {
+                    toggles.push((on.clone(), off.clone()));
+                    actions.extend([on.clone(), off.clone()]);
+                }
+                _ => return Err(format!("{}: cannot migrate action option {}", legacy.label, name)),
+            }
+        }
+    }
+
+    // The old placeholder was a bare ACTION word
+    let template = legacy.command
+        .split(' ')
+        .map(|word| if word == "ACTION" { "{ACTION}" } else { word })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut item = MenuItemConfig::new(&legacy.label, &template);
+    item.state_machine = legacy_machine(&actions, &toggles);
+    Ok(item)
+}
+
+// CommandInfo lists hold one full command per action, named "<label>
+// <action>". Commands that do not fit the item's template keep their exact
+// text on the transition.
+fn migrate_commands(commands: &[CommandInfo]) -> Vec<MenuItemConfig> {
+    // Grouped by category in the order they first appear
+    let mut groups: Vec<(&str, Vec<&CommandInfo>)> = Vec::new();
+    for command in commands {
+        match groups.iter_mut().find(|(category, _)| *category == command.category) {
+            Some((_, group)) => group.push(command),
+            None => groups.push((&command.category, vec![command])),
+        }
+    }
+
+    let to_template = |command: &str, action: &str| {
+        let mut replaced = false;
+        command.split(' ')
+            .map(|word| if word == action && !replaced { replaced = true; "{ACTION}" } else { word })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    groups.into_iter()
+        .map(|(category, group)| {
+            let actions: Vec<(String, &str)> = group.iter()
+                .map(|command| {
+                    let action = command.name.rsplit(' ').next().unwrap_or(&command.name).to_string();
+                    (action, command.command.as_str())
+                })
+                .collect();
+            let template = to_template(actions[0].1, &actions[0].0);
+
+            let mut names: Vec<String> = actions.iter().map(|(action, _)| action.clone()).collect();
+            names.sort();
+            names.dedup();
+            let has = |name: &str| names.iter().any(|action| action == name);
+            let toggles = if has("freeze") && has("unfreeze") {
+                vec![("freeze".to_string(), "unfreeze".to_string())]
+            } else {
+                Vec::new()
+            };
+
+            let mut item = MenuItemConfig::new(category, &template);
+            item.state_machine = legacy_machine(&names, &toggles);
+            for (action, command) in &actions {
+                if to_template(command, action) == template {
+                    continue;
+                }
+                let overridden = item.state_machine.states.values_mut()
+                    .filter_map(|state| state.transitions.get_mut(action));
+                for transition in overridden {
+                    transition.command = Some(command.to_string());
+                }
+            }
+            item
+        })
+        .collect()
+}
+
+// A state machine that offers the same actions the old formats did: start
+// brings an item up, kill and stop take it down, each toggle pair moves it
+// to a state of its own and back, and anything else is always available
+fn legacy_machine(actions: &[String], toggles: &[(String, String)]) -> StateMachine {
+    let standard = StateMachine::standard();
+    let has = |name: &str| actions.iter().any(|action| action == name);
+    let toggled = |name: &str| toggles.iter().any(|(on, off)| on == name || off == name);
+
+    // States the standard machine has keep its colors
+    let new_state = |name: &str| State {
+        display: standard.states.get(name).map(|state| state.display.clone()).unwrap_or_default(),
+        transitions: BTreeMap::new(),
+        style: BTreeMap::new(),
+        on_enter: Vec::new(),
+        on_exit: Vec::new(),
+        after: None,
+        parent: None,
+        initial: None,
+    };
+
+    let mut states = BTreeMap::from([("default".to_string(), new_state("default"))]);
+    let add = |states: &mut BTreeMap<String, State>, from: &str, action: &str, to: &str| {
+        for name in [from, to] {
+            states.entry(name.to_string()).or_insert_with(|| new_state(name));
+        }
+        if let Some(state) = states.get_mut(from) {
+            state.transitions.insert(action.to_string(), Transition::to(to));
+        }
+    };
+
+    let up = if has("start") { "started" } else { "default" };
+    let downs: Vec<&str> = ["kill", "stop"].into_iter()
+        .filter(|down| has("start") && has(down))
+        .collect();
+
+    if has("start") {
+        add(&mut states, "default", "start", "started");
+        for down in &downs {
+            add(&mut states, "started", down, "killed");
+            add(&mut states, "killed", "start", "started");
+        }
+    }
+    for (on, off) in toggles {
+        let toggled_state = if on == "freeze" { "frozen" } else { on.as_str() };
+        add(&mut states, up, on, toggled_state);
+        add(&mut states, toggled_state, off, up);
+        for down in &downs {
+            add(&mut states, toggled_state, down, "killed");
+        }
+    }
+
+    let names: Vec<String> = states.keys().cloned().collect();
+    for action in actions {
+        if action == "start" || downs.contains(&action.as_str()) || toggled(action) {
+            continue;
+        }
+        for name in &names {
+            add(&mut states, name, action, name);
+        }
+    }
+
+    StateMachine {
+        initial_state: "default".to_string(),
+        states,
+        regions: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transitions(item: &MenuItemConfig, state: &str) -> Vec<(String, String)> {
+        item.state_machine.states[state].transitions.iter()
+            .map(|(action, transition)| (action.clone(), transition.to.clone()))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(action, to)| (action.to_string(), to.to_string())).collect()
+    }
+
+    #[test]
+    fn legacy_items_get_a_machine_for_their_actions_and_toggles() {
+        let config = migrate_config(
+            "menu_items:
+- label: work
+  command: ./Menu_Runner_system ACTION firefox work
+  actions:
+    kill: {}
+    snapshot: {}
+    start:
+      freeze_toggle: [freeze, unfreeze]
+",
+        )
+        .unwrap();
+        let item = &config.menu_items[0];
+
+        assert_eq!(item.command_template, "./Menu_Runner_system {ACTION} firefox work");
+        assert_eq!(transitions(item, "default"), pairs(&[("snapshot", "default"), ("start", "started")]));
+        assert_eq!(transitions(item, "started"), pairs(&[("freeze", "frozen"), ("kill", "killed"), ("snapshot", "started")]));
+        assert_eq!(transitions(item, "frozen"), pairs(&[("kill", "killed"), ("snapshot", "frozen"), ("unfreeze", "started")]));
+        assert_eq!(transitions(item, "killed"), pairs(&[("snapshot", "killed"), ("start", "started")]));
+        assert_eq!(item.state_machine.states["frozen"].display, StateMachine::standard().states["frozen"].display);
+    }
+
+    #[test]
+    fn legacy_json_reads_the_same_as_yaml() {
+        let json = migrate_config(r#"{"menu_items": [{"label": "a", "command": "run ACTION", "actions": {"start": {}, "stop": {}}}]}"#).unwrap();
+        let yaml = migrate_config("menu_items:\n- label: a\n  command: run ACTION\n  actions:\n    start: {}\n    stop: {}\n").unwrap();
+
+        assert_eq!(json.menu_items[0].state_machine, yaml.menu_items[0].state_machine);
+        assert_eq!(transitions(&json.menu_items[0], "started"), pairs(&[("stop", "killed")]));
+    }
+
+    #[test]
+    fn unknown_legacy_options_are_errors() {
+        let error = migrate_config("menu_items:\n- label: a\n  command: run ACTION\n  actions:\n    start:\n      mute: [on]\n").unwrap_err();
+        assert_eq!(error, "a: cannot migrate action option mute");
+    }
+
+    #[test]
+    fn command_lists_become_one_item_per_category() {
+        let config = migrate_config(
+            "- name: work start
+  command: sys start firefox work
+  category: work
+- name: work kill
+  command: sys kill firefox work
+  category: work
+- name: work snapshot
+  command: backup-tool work
+  category: work
+- name: chat start
+  command: sys start chat
+  category: chat
+",
+        )
+        .unwrap();
+        let labels: Vec<&str> = config.menu_items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["work", "chat"]);
+
+        let work = &config.menu_items[0];
+        assert_eq!(work.command_template, "sys {ACTION} firefox work");
+        assert_eq!(transitions(work, "started"), pairs(&[("kill", "killed"), ("snapshot", "started")]));
+        // a command that does not fit the template keeps its own text
+        let snapshot = work.state_machine.transition("default", "snapshot").unwrap();
+        assert_eq!(snapshot.command.as_deref(), Some("backup-tool work"));
+        assert_eq!(work.state_machine.transition("default", "start").unwrap().command, None);
+    }
+
+    #[test]
+    fn current_configs_are_kept_as_they_are() {
+        let content = "menu_items:
+- label: vpn
+  command_template: vpn {ACTION}
+  state_machine:
+    initial_state: down
+    states:
+      down:
+        transitions:
+          start: up
+      up:
+        transitions:
+          kill: down
+";
+        let config = migrate_config(content).unwrap();
+
+        assert_eq!(config.menu_items[0].command_template, "vpn {ACTION}");
+        assert_eq!(config.menu_items[0].state_machine.initial_state, "down");
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), serde_yaml::to_string(&serde_yaml::from_str::<MenuConfig>(content).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn other_content_is_rejected() {
+        assert!(migrate_config("- just\n- a list").unwrap_err().starts_with("Not a menu config"));
+    }
+}
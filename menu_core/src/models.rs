@@ -1,24 +1,9 @@
 /// menu_core/src/models.rs
-/// Represents a single menu command entry
-use std::collections::HashMap;
+/// Menu config, item state machines and the button manager
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
-
-// Basic Command Information structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CommandInfo {
-    pub name: String,
-    pub command: String,
-    pub description: String,
-    pub category: String,
-}
-
-// Structure for grouped menu entries
-pub struct GroupedMenuEntry {
-    pub program: String,
-    pub path_name: String,
-    pub actions: Vec<String>,
-    pub commands: Vec<String>,
-}
+use crate::state_file::{SavedState, SavedStates};
 
 // Structure for Slint menu entries
 #[derive(Debug, Clone)]
@@ -28,12 +13,88 @@ pub struct SlintMenuEntry {
     pub command_template: String,
 }
 
+impl SlintMenuEntry {
+    // The entry of a menu item: every action of any of its states, regions
+    // included, and its template exactly as configured
+    pub fn from_item(item: &MenuItemConfig) -> Self {
+        SlintMenuEntry {
+            label: item.label.clone(),
+            actions: item.state_machine.all_actions(),
+            command_template: item.command_template.clone(),
+        }
+    }
+}
+
 // State in the state machine
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Maps are ordered so a config written back to disk keeps a stable layout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct State {
-    pub transitions: HashMap<String, String>,
-    #[serde(default)]  // Make style field optional with default empty HashMap
-    pub style: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub display: BTreeMap<String, String>,
+    #[serde(default)]
+    pub transitions: BTreeMap<String, Transition>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]  // Make style field optional with default empty map
+    pub style: BTreeMap<String, String>,
+    // Shell commands run when an item enters or leaves the state, with the
+    // same {ACTION} placeholder as command_template
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_enter: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_exit: Vec<String>,
+    // Press an action on its own once the item has been in the state this long
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<After>,
+    // The state this one is nested in. Its transitions, hooks and timer
+    // apply to every state inside it unless a nested state overrides them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    // For a state with nested states: the one entering it leads to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial: Option<String>,
+}
+
+// A timed transition, e.g. `after: {duration: 2h, action: kill}`. When the
+// action's guards do not hold yet it is tried again after the same duration,
+// so a short duration with a probe guard waits for something to come up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct After {
+    pub duration: String,
+    pub action: String,
+}
+
+// Durations like "30s", "5m", "2h" or "1h30m"; a bare number is seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration: {} (expected e.g. 30s, 5m, 2h or 1h30m)", text);
+    let mut seconds = 0u64;
+    let mut number = String::new();
+
+    for c in text.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        seconds = value.checked_mul(unit)
+            .and_then(|value| seconds.checked_add(value))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        seconds = seconds.checked_add(value).ok_or_else(invalid)?;
+    }
+
+    if text.trim().is_empty() {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
 }
 
 impl State {
@@ -43,25 +104,919 @@ impl State {
     }
 }
 
+// Where an action leads. A transition with nothing but a target state is
+// written as just that state, e.g. `start: started`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "TransitionDef", into = "TransitionDef")]
+pub struct Transition {
+    pub to: String,
+    // Conditions that must all hold before the action may run
+    pub guards: Vec<Guard>,
+    // Runs instead of the item's command_template; {ACTION} works the same
+    pub command: Option<String>,
+    // Extra environment for the command
+    pub env: BTreeMap<String, String>,
+    pub working_dir: Option<String>,
+    // The command is killed and the action fails after this long
+    pub timeout_secs: Option<u64>,
+    // The action the command runs when it is not the one pressed, e.g. a
+    // kill button running the graceful `stop` of Menu_Runner_system
+    pub action: Option<String>,
+}
+
+impl Transition {
+    pub fn to(state: &str) -> Self {
+        Transition {
+            to: state.to_string(),
+            guards: Vec::new(),
+            command: None,
+            env: BTreeMap::new(),
+            working_dir: None,
+            timeout_secs: None,
+            action: None,
+        }
+    }
+
+    // The action `pressed` runs as
+    pub fn runs<'a>(&'a self, pressed: &'a str) -> &'a str {
+        self.action.as_deref().unwrap_or(pressed)
+    }
+
+    // Whether the transition changes how its action's command runs
+    pub fn has_command_settings(&self) -> bool {
+        self.command.is_some() || !self.env.is_empty() || self.working_dir.is_some() || self.timeout_secs.is_some() || self.action.is_some()
+    }
+}
+
+// How a transition is written in the config
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum TransitionDef {
+    Target(String),
+    Full {
+        to: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        guards: Vec<Guard>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command: Option<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        working_dir: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        action: Option<String>,
+    },
+}
+
+impl From<TransitionDef> for Transition {
+    fn from(def: TransitionDef) -> Self {
+        match def {
+            TransitionDef::Target(to) => Transition::to(&to),
+            TransitionDef::Full { to, guards, command, env, working_dir, timeout_secs, action } => {
+                Transition { to, guards, command, env, working_dir, timeout_secs, action }
+            }
+        }
+    }
+}
+
+impl From<Transition> for TransitionDef {
+    fn from(transition: Transition) -> Self {
+        if transition.guards.is_empty() && !transition.has_command_settings() {
+            return TransitionDef::Target(transition.to);
+        }
+
+        let Transition { to, guards, command, env, working_dir, timeout_secs, action } = transition;
+        TransitionDef::Full { to, guards, command, env, working_dir, timeout_secs, action }
+    }
+}
+
+// A shell command ready to run for an action
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionCommand {
+    pub command: String,
+    pub env: BTreeMap<String, String>,
+    pub working_dir: Option<String>,
+    pub timeout_secs: Option<u64>,
+}
+
+// A condition on a transition, checked right before its action runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Guard {
+    // `file_exists: /run/user/1000/vpn.sock`
+    FileExists { file_exists: String },
+    // `env: VPN_TOKEN`, set and not empty
+    Env { env: String },
+    // `probe: "ping -c1 -W1 10.8.0.1"`, run with sh and must exit 0
+    Probe { probe: String },
+    // `item: vpn` with `state: started`
+    ItemState { item: String, state: String },
+}
+
 // State machine definition
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StateMachine {
     pub initial_state: String,
-    pub states: HashMap<String, State>,
+    pub states: BTreeMap<String, State>,
+    // Machines of their own that run alongside this one, e.g. audio muted
+    // or not while a profile is started
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub regions: BTreeMap<String, Region>,
+}
+
+// An orthogonal region of an item's state machine. Its actions show up next
+// to the main machine's and only move the region.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    // Only active while the main machine is in this state or one nested in
+    // it; leaving it puts the region back to its initial state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub within: Option<String>,
+    #[serde(flatten)]
+    pub machine: StateMachine,
+}
+
+impl StateMachine {
+    // The state and the states it is nested in, innermost first
+    pub fn ancestors<'a>(&'a self, state: &'a str) -> Vec<&'a str> {
+        let mut chain = vec![state];
+        while let Some(parent) = self.states.get(chain[chain.len() - 1]).and_then(|s| s.parent.as_deref()) {
+            // Stop at a cycle, which validation reports
+            if chain.contains(&parent) {
+                break;
+            }
+            chain.push(parent);
+        }
+        chain
+    }
+
+    // Whether `state` is `outer` or nested somewhere inside it
+    pub fn is_in(&self, state: &str, outer: &str) -> bool {
+        self.ancestors(state).contains(&outer)
+    }
+
+    // The innermost state entering `state` ends up in, following `initial`
+    pub fn resolve(&self, state: &str) -> String {
+        let mut current = state.to_string();
+        for _ in 0..self.states.len() {
+            match self.states.get(&current).and_then(|s| s.initial.clone()) {
+                Some(initial) => current = initial,
+                None => break,
+            }
+        }
+        current
+    }
+
+    pub fn initial(&self) -> String {
+        self.resolve(&self.initial_state)
+    }
+
+    // The transition `action` takes from `state`: the state's own, or else
+    // the one of the nearest state it is nested in
+    pub fn transition(&self, state: &str, action: &str) -> Option<&Transition> {
+        self.ancestors(state)
+            .into_iter()
+            .find_map(|s| self.states.get(s)?.transitions.get(action))
+    }
+
+    // Every action available in `state`, sorted
+    pub fn actions(&self, state: &str) -> Vec<String> {
+        let actions: std::collections::BTreeSet<&String> = self.ancestors(state)
+            .into_iter()
+            .filter_map(|s| self.states.get(s))
+            .flat_map(|s| s.transitions.keys())
+            .collect();
+        actions.into_iter().cloned().collect()
+    }
+
+    // Every action of any state, in this machine or its regions
+    pub fn all_actions(&self) -> Vec<String> {
+        let mut actions: Vec<String> = self.states.values()
+            .flat_map(|state| state.transitions.keys().cloned())
+            .chain(self.regions.values().flat_map(|region| region.machine.all_actions()))
+            .collect();
+        actions.sort();
+        actions.dedup();
+        actions
+    }
+
+    // Every transition of any state, in this machine or its regions
+    pub fn all_transitions(&self) -> Vec<&Transition> {
+        self.states.values()
+            .flat_map(|state| state.transitions.values())
+            .chain(self.regions.values().flat_map(|region| region.machine.all_transitions()))
+            .collect()
+    }
+
+    // The nearest state with an `after:` timer that `state` is in
+    pub fn timer_state<'a>(&'a self, state: &'a str) -> Option<(&'a str, &'a After)> {
+        self.ancestors(state)
+            .into_iter()
+            .find_map(|s| Some((s, self.states.get(s)?.after.as_ref()?)))
+    }
+
+    // Hooks for moving from one state to another: on_exit of every state
+    // left, innermost first, then on_enter of every state entered, outermost
+    // first. States both are nested in are not left.
+    pub fn hooks_between(&self, from: &str, to: &str) -> Vec<&String> {
+        let left = self.ancestors(from);
+        let entered = self.ancestors(to);
+
+        let exits = left.iter()
+            .filter(|s| !entered.contains(s))
+            .filter_map(|s| self.states.get(*s))
+            .flat_map(|s| &s.on_exit);
+        let enters = entered.iter()
+            .rev()
+            .filter(|s| !left.contains(s))
+            .filter_map(|s| self.states.get(*s))
+            .flat_map(|s| &s.on_enter);
+        exits.chain(enters).collect()
+    }
+
+    // Nesting and regions must be consistent: parents and initial states
+    // exist, initial states are nested in their state, nothing is nested in
+    // itself, and regions do not share actions with the main machine
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, state) in &self.states {
+            if let Some(parent) = &state.parent {
+                if !self.states.contains_key(parent) {
+                    return Err(format!("state {} is nested in unknown state {}", name, parent));
+                }
+            }
+            if let Some(initial) = &state.initial {
+                if !self.states.contains_key(initial) || initial == name || !self.is_in(initial, name) {
+                    return Err(format!("initial state {} of {} is not nested in it", initial, name));
+                }
+            }
+
+            let chain = self.ancestors(name);
+            let last = chain[chain.len() - 1];
+            if let Some(parent) = self.states.get(last).and_then(|s| s.parent.as_deref()) {
+                return Err(format!("state {} is nested in itself through {}", parent, last));
+            }
+        }
+        if !self.states.contains_key(&self.initial_state) {
+            return Err(format!("unknown initial state {}", self.initial_state));
+        }
+
+        let main_actions = self.all_actions_of_states();
+        for (name, region) in &self.regions {
+            if !region.machine.regions.is_empty() {
+                return Err(format!("region {} has regions of its own", name));
+            }
+            region.machine.validate().map_err(|e| format!("region {}: {}", name, e))?;
+            if let Some(within) = &region.within {
+                if !self.states.contains_key(within) {
+                    return Err(format!("region {} is within unknown state {}", name, within));
+                }
+            }
+
+            let region_actions = region.machine.all_actions();
+            if let Some(shared) = region_actions.iter().find(|action| main_actions.contains(action)) {
+                return Err(format!("region {} uses action {}, which the main states use too", name, shared));
+            }
+            for (other_name, other) in &self.regions {
+                if other_name < name {
+                    if let Some(shared) = region_actions.iter().find(|action| other.machine.all_actions().contains(action)) {
+                        return Err(format!("regions {} and {} both use action {}", other_name, name, shared));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn all_actions_of_states(&self) -> Vec<String> {
+        self.states.values().flat_map(|state| state.transitions.keys().cloned()).collect()
+    }
+
+    // The default/started/frozen/killed machine of items that do not define
+    // their own. Kill runs the graceful stop, which asks the app to exit and
+    // only kills it after a grace period; force_kill kills it straight away.
+    // Snapshots are left to the machines of profile items.
+    pub fn standard() -> Self {
+        let transition = |action: &str, next: &str| {
+            let mut transition = Transition::to(next);
+            if action == "kill" {
+                transition.action = Some("stop".to_string());
+            }
+            (action.to_string(), transition)
+        };
+        let state = |bg: &str, fg: &str, transitions: &[(&str, &str)]| State {
+            display: BTreeMap::from([
+                ("bg".to_string(), bg.to_string()),
+                ("fg".to_string(), fg.to_string()),
+            ]),
+            transitions: transitions
+                .iter()
+                .map(|(action, next)| transition(action, next))
+                .collect(),
+            style: BTreeMap::new(),
+            on_enter: Vec::new(),
+            on_exit: Vec::new(),
+            after: None,
+            parent: None,
+            initial: None,
+        };
+
+        let mut states = BTreeMap::new();
+        states.insert(
+            "default".to_string(),
+            state("#2E2E2E", "#D3D3D3", &[("start", "started")]),
+        );
+        states.insert(
+            "frozen".to_string(),
+            state("#ADD8E6", "#000000", &[("force_kill", "killed"), ("kill", "killed"), ("unfreeze", "started")]),
+        );
+        states.insert(
+            "killed".to_string(),
+            state("#8B0000", "#FFFFFF", &[("start", "started")]),
+        );
+        states.insert(
+            "started".to_string(),
+            state("#228B22", "#FFFFFF", &[("force_kill", "killed"), ("freeze", "frozen"), ("kill", "killed")]),
+        );
+
+        StateMachine {
+            initial_state: "default".to_string(),
+            states,
+            regions: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_standard(&self) -> bool {
+        *self == Self::standard()
+    }
+}
+
+// Container resource limits passed through to Menu_Runner_system as flags
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blkio_weight: Option<u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_read_bps: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_write_bps: Vec<String>,
+}
+
+impl ResourceLimits {
+    // Command line flags for Menu_Runner_system, e.g. ["--memory", "2g"]
+    pub fn to_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        let mut push = |name: &str, value: String| {
+            flags.push(name.to_string());
+            flags.push(value);
+        };
+
+        if let Some(cpus) = &self.cpus {
+            push("--cpus", cpus.clone());
+        }
+        if let Some(memory) = &self.memory {
+            push("--memory", memory.clone());
+        }
+        if let Some(pids_limit) = self.pids_limit {
+            push("--pids-limit", pids_limit.to_string());
+        }
+        if let Some(blkio_weight) = self.blkio_weight {
+            push("--blkio-weight", blkio_weight.to_string());
+        }
+        for rate in &self.device_read_bps {
+            push("--device-read-bps", rate.clone());
+        }
+        for rate in &self.device_write_bps {
+            push("--device-write-bps", rate.clone());
+        }
+
+        flags
+    }
+}
+
+// How a menu item's actions are executed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Runner {
+    // Expand command_template and run it through `sh -c`
+    #[default]
+    Shell,
+    // Call the Menu_Runner_system library in-process with the item's app and profile
+    System,
+}
+
+impl Runner {
+    pub fn is_shell(&self) -> bool {
+        *self == Runner::Shell
+    }
+}
+
+// Container runtime events and the state each one puts a menu item in
+pub const DEFAULT_EVENT_STATES: [(&str, &str); 6] = [
+    ("start", "started"),
+    ("unpause", "started"),
+    ("pause", "frozen"),
+    ("die", "killed"),
+    // Reported by the supervisor for items with `supervise`
+    ("crash", "crashed"),
+    ("restart", "restarting"),
+];
+
+fn default_required_state() -> String {
+    "started".to_string()
+}
+
+// Another menu item that has to be in `state` before this one comes up,
+// e.g. a VPN container the profile routes its traffic through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Requirement {
+    pub label: String,
+    #[serde(default = "default_required_state")]
+    pub state: String,
+    // Bring the prerequisite into `state` first instead of refusing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_start: bool,
+}
+
+// When the supervisor starts a process again after it exited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+fn default_backoff_secs() -> u64 {
+    1
+}
+
+fn default_max_backoff_secs() -> u64 {
+    60
+}
+
+// Supervision settings for long-running menu items. The backoff doubles with
+// each restart up to max_backoff_secs, and starts over once the process has
+// stayed up that long.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorConfig {
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    // Give up and leave the item crashed after this many restarts in a row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_restarts: Option<u32>,
+    #[serde(default = "default_backoff_secs")]
+    pub backoff_secs: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
 }
 
 // Menu item configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MenuItemConfig {
     pub label: String,
+    #[serde(default)]  // Not needed by items using `runner: system`
     pub command_template: String,
+    // Items without one get the standard start/freeze/kill machine
+    #[serde(default = "StateMachine::standard", skip_serializing_if = "StateMachine::is_standard")]
     pub state_machine: StateMachine,
+    #[serde(default, skip_serializing_if = "Runner::is_shell")]
+    pub runner: Runner,
+    // App and profile directory for `runner: system`; shell items take them
+    // from the arguments after {ACTION} in command_template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<ResourceLimits>,
+    // Menu_Runner_system backend: cli, api or native. Unset means
+    // MENU_RUNNER_BACKEND or the docker CLI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    // Network mode for the profile container: none, host, default,
+    // proxy:<container> or the name of a Docker network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    // Host directory shared as the profile's Downloads folder, optionally
+    // split into one subdirectory per profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloads: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub downloads_per_profile: bool,
+    // Clipboard policy for the profile: shared or isolated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<String>,
+    // Items that must be up before this one starts; it is stopped again
+    // when one of them goes down
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<Requirement>,
+    // Groups the item belongs to, for bulk actions on part of the menu
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    // Keep the process started by the start action and restart it by policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supervise: Option<SupervisorConfig>,
+    // Container runtime events mapped to the state they put the item in,
+    // overriding DEFAULT_EVENT_STATES
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub events: BTreeMap<String, String>,
+}
+
+impl MenuItemConfig {
+    pub fn new(label: &str, command_template: &str) -> Self {
+        MenuItemConfig {
+            label: label.to_string(),
+            command_template: command_template.to_string(),
+            state_machine: StateMachine::standard(),
+            runner: Runner::Shell,
+            app: None,
+            profile: None,
+            limits: None,
+            backend: None,
+            network: None,
+            downloads: None,
+            downloads_per_profile: false,
+            clipboard: None,
+            requires: Vec::new(),
+            groups: Vec::new(),
+            supervise: None,
+            events: BTreeMap::new(),
+        }
+    }
+
+    // The app and profile directory this item manages: the `app`/`profile`
+    // fields if set, otherwise the arguments following {ACTION} in a
+    // Menu_Runner_system command template
+    pub fn target(&self) -> Option<(String, String)> {
+        if let (Some(app), Some(profile)) = (&self.app, &self.profile) {
+            return Some((app.clone(), profile.clone()));
+        }
+
+        let mut parts = self.command_template.split_whitespace();
+        parts.find(|part| *part == "{ACTION}")?;
+        let app = parts.next()?;
+        let profile = parts.next()?;
+        Some((app.to_string(), profile.to_string()))
+    }
+
+    // The part of the command template before {ACTION}, e.g.
+    // ./target/debug/Menu_Runner_system
+    pub fn runner_command(&self) -> Option<String> {
+        let (runner, _) = self.command_template.split_once("{ACTION}")?;
+        let runner = runner.trim();
+        (!runner.is_empty()).then(|| runner.to_string())
+    }
+
+    // The --backend and --docker-socket flags the item's actions run with,
+    // from the item's own settings or its command template. Items with the
+    // same ones share a container event stream.
+    pub fn backend_flags(&self) -> Vec<String> {
+        let template = self.command_template.split_whitespace().map(str::to_string);
+        let words: Vec<String> = template.chain(self.option_flags()).collect();
+
+        let mut flags = Vec::new();
+        for name in ["--backend", "--docker-socket"] {
+            if let Some(value) = words.iter().position(|word| word == name).and_then(|at| words.get(at + 1)) {
+                flags.push(name.to_string());
+                flags.push(value.clone());
+            }
+        }
+        flags
+    }
+
+    // Menu_Runner_system names containers app.<app>-<profile directory name>
+    pub fn container_name(&self) -> Option<String> {
+        let (app, profile) = self.target()?;
+        let profile_part = profile.trim_end_matches('/').rsplit('/').next()?.to_string();
+        Some(format!("app.{}-{}", app, profile_part))
+    }
+
+    // The state a container runtime event moves this item to, if the state machine has it
+    pub fn state_for_event(&self, event: &str) -> Option<String> {
+        let state = self.events.get(event).cloned().or_else(|| {
+            DEFAULT_EVENT_STATES.iter()
+                .find(|(name, _)| *name == event)
+                .map(|(_, state)| state.to_string())
+        })?;
+        
+        self.state_machine.states.contains_key(&state).then_some(state)
+    }
+
+    // The command for an action taking `transition`: its own command if it
+    // has one, otherwise the expanded command template
+    pub fn action_command(&self, action: &str, transition: Option<&Transition>) -> ActionCommand {
+        let action = transition.map_or(action, |transition| transition.runs(action));
+        let command = match transition.and_then(|transition| transition.command.as_ref()) {
+            Some(command) => command.replace("{ACTION}", action),
+            None => self.build_command(action),
+        };
+
+        ActionCommand {
+            command,
+            env: transition.map(|transition| transition.env.clone()).unwrap_or_default(),
+            working_dir: transition.and_then(|transition| transition.working_dir.clone()),
+            timeout_secs: transition.and_then(|transition| transition.timeout_secs),
+        }
+    }
+
+    // Expand the command template for an action, appending any per-item flags
+    pub fn build_command(&self, action: &str) -> String {
+        let mut command = self.command_template.replace("{ACTION}", action);
+
+        for flag in self.option_flags() {
+            command.push(' ');
+            command.push_str(&flag);
+        }
+
+        command
+    }
+
+    // Menu_Runner_system flags for the item's limits, backend, network and sharing options
+    pub fn option_flags(&self) -> Vec<String> {
+        let mut flags = self.limits.as_ref().map(|limits| limits.to_flags()).unwrap_or_default();
+
+        if let Some(backend) = &self.backend {
+            flags.push("--backend".to_string());
+            flags.push(backend.clone());
+        }
+        if let Some(network) = &self.network {
+            flags.push("--network".to_string());
+            flags.push(network.clone());
+        }
+        if let Some(downloads) = &self.downloads {
+            flags.push("--downloads".to_string());
+            flags.push(downloads.clone());
+            if self.downloads_per_profile {
+                flags.push("--downloads-per-profile".to_string());
+            }
+        }
+        if let Some(clipboard) = &self.clipboard {
+            flags.push("--clipboard".to_string());
+            flags.push(clipboard.clone());
+        }
+
+        flags
+    }
 }
 
 // Overall menu configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MenuConfig {
+    // Other config files or glob patterns, relative to this file, read
+    // before it so it can override their items
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // Labels of items from includes or earlier layers to leave out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+    #[serde(default)]
     pub menu_items: Vec<MenuItemConfig>,
+    // How many items a bulk action works on at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallelism: Option<usize>,
+    // Named sets of items started and stopped together
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sessions: BTreeMap<String, Session>,
+}
+
+fn default_session_action() -> String {
+    "start".to_string()
+}
+
+// Runs the graceful stop on the standard machine
+fn default_session_stop_action() -> String {
+    "kill".to_string()
+}
+
+// A menu item in a session and the actions that bring it up and down
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionItem {
+    pub label: String,
+    #[serde(default = "default_session_action")]
+    pub action: String,
+    #[serde(default = "default_session_stop_action")]
+    pub stop_action: String,
+    // Items run in ascending order, then in the order they are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<i32>,
+    // Seconds to wait before starting this item, e.g. for a VPN to come up
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub delay_secs: u64,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub items: Vec<SessionItem>,
+}
+
+// One step of running a session
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionStep {
+    pub label: String,
+    pub action: String,
+    pub delay_secs: u64,
+}
+
+impl Session {
+    // Starting goes through the items in order with their delays; stopping
+    // goes the other way round without waiting
+    pub fn steps(&self, stop: bool) -> Vec<SessionStep> {
+        let mut items: Vec<&SessionItem> = self.items.iter().collect();
+        items.sort_by_key(|item| item.order.unwrap_or(0));
+
+        if stop {
+            items.iter().rev()
+                .map(|item| SessionStep { label: item.label.clone(), action: item.stop_action.clone(), delay_secs: 0 })
+                .collect()
+        } else {
+            items.iter()
+                .map(|item| SessionStep { label: item.label.clone(), action: item.action.clone(), delay_secs: item.delay_secs })
+                .collect()
+        }
+    }
+}
+
+impl MenuConfig {
+    // Lay `other` over this config. Its `remove:` labels are dropped first.
+    // An item replaces the item with the same label where that one stands,
+    // and other items are added at the end. Sessions replace the session of
+    // the same name and a parallelism that is set wins.
+    pub fn merge(&mut self, other: MenuConfig) {
+        self.menu_items.retain(|item| !other.remove.contains(&item.label));
+        for item in other.menu_items {
+            match self.menu_items.iter_mut().find(|existing| existing.label == item.label) {
+                Some(existing) => *existing = item,
+                None => self.menu_items.push(item),
+            }
+        }
+        self.sessions.extend(other.sessions);
+        if other.parallelism.is_some() {
+            self.parallelism = other.parallelism;
+        }
+    }
+
+    // Every `requires:` entry and item state guard must name an existing
+    // item, and following the requirements must never lead back to where
+    // it started
+    pub fn validate_requirements(&self) -> Result<(), String> {
+        for item in &self.menu_items {
+            for requirement in &item.requires {
+                let required = self.menu_items.iter()
+                    .find(|other| other.label == requirement.label)
+                    .ok_or_else(|| format!("{} requires unknown item {}", item.label, requirement.label))?;
+                if !required.state_machine.states.contains_key(&requirement.state) {
+                    return Err(format!(
+                        "{} requires {} to be {}, which is not one of its states",
+                        item.label, requirement.label, requirement.state
+                    ));
+                }
+            }
+
+            // Item state guards name other items the same way
+            let guarded_items = item.state_machine.all_transitions()
+                .into_iter()
+                .flat_map(|transition| &transition.guards)
+                .filter_map(|guard| match guard {
+                    Guard::ItemState { item, .. } => Some(item),
+                    _ => None,
+                });
+            for guarded in guarded_items {
+                if !self.menu_items.iter().any(|other| &other.label == guarded) {
+                    return Err(format!("{} has a guard on unknown item {}", item.label, guarded));
+                }
+            }
+        }
+
+        // Depth-first search, keeping the current path to report the cycle
+        fn visit<'a>(config: &'a MenuConfig, label: &'a str, path: &mut Vec<&'a str>, done: &mut Vec<&'a str>) -> Result<(), String> {
+            if let Some(start) = path.iter().position(|l| *l == label) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(label);
+                return Err(format!("Circular requirement: {}", cycle.join(" -> ")));
+            }
+            if done.contains(&label) {
+                return Ok(());
+            }
+
+            path.push(label);
+            if let Some(item) = config.menu_items.iter().find(|item| item.label == label) {
+                for requirement in &item.requires {
+                    visit(config, &requirement.label, path, done)?;
+                }
+            }
+            path.pop();
+            done.push(label);
+            Ok(())
+        }
+
+        let mut done = Vec::new();
+        for item in &self.menu_items {
+            visit(self, &item.label, &mut Vec::new(), &mut done)?;
+        }
+        Ok(())
+    }
+
+    // Every item's states must nest consistently, see StateMachine::validate
+    pub fn validate_state_machines(&self) -> Result<(), String> {
+        for item in &self.menu_items {
+            item.state_machine.validate().map_err(|e| format!("{}: {}", item.label, e))?;
+        }
+        Ok(())
+    }
+
+    // Every `after:` needs a valid duration and an action its state has,
+    // and only the main machine has timers
+    pub fn validate_timers(&self) -> Result<(), String> {
+        for item in &self.menu_items {
+            for (region_name, region) in &item.state_machine.regions {
+                if let Some((name, _)) = region.machine.states.iter().find(|(_, state)| state.after.is_some()) {
+                    return Err(format!("{} region {} state {}: regions cannot have timers", item.label, region_name, name));
+                }
+            }
+            for (name, state) in &item.state_machine.states {
+                let Some(after) = &state.after else { continue };
+                parse_duration(&after.duration).map_err(|e| format!("{} state {}: {}", item.label, name, e))?;
+                if !item.state_machine.actions(name).contains(&after.action) {
+                    return Err(format!(
+                        "{} state {} waits to {}, which is not one of its actions",
+                        item.label, name, after.action
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Whether any state has an `after:` timer
+    pub fn has_timers(&self) -> bool {
+        self.menu_items.iter()
+            .flat_map(|item| item.state_machine.states.values())
+            .any(|state| state.after.is_some())
+    }
+
+    // Whether any transition has a guard
+    pub fn has_guards(&self) -> bool {
+        self.menu_items.iter()
+            .flat_map(|item| item.state_machine.all_transitions())
+            .any(|transition| !transition.guards.is_empty())
+    }
+
+    // Group names used by any item, sorted
+    pub fn groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = self.menu_items.iter()
+            .flat_map(|item| item.groups.iter().cloned())
+            .collect();
+        groups.sort();
+        groups.dedup();
+        groups
+    }
+}
+
+// The menu items a bulk action applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    All,
+    Group(String),
+    Labels(Vec<String>),
+}
+
+impl Selection {
+    pub fn matches(&self, item: &MenuItemConfig) -> bool {
+        match self {
+            Selection::All => true,
+            Selection::Group(group) => item.groups.contains(group),
+            Selection::Labels(labels) => labels.contains(&item.label),
+        }
+    }
+}
+
+// An on_exit or on_enter command of a state an item moved out of or into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hook {
+    pub label: String,
+    pub command: String,
+}
+
+// A running `after:` timer of an item
+#[derive(Debug, Clone)]
+struct ArmedTimer {
+    state: String,
+    action: String,
+    duration: Duration,
+    due: Instant,
 }
 
 // Button manager that tracks button states
@@ -69,14 +1024,31 @@ pub struct ButtonManager {
     pub button_states: HashMap<String, String>,
     pub button_colors: HashMap<String, String>,
     pub menu_config: Option<MenuConfig>,
+    // Hooks of state changes the caller has not run yet, oldest first
+    pending_hooks: Vec<Hook>,
+    // `after:` timers by item label, armed when the item enters the state
+    timers: HashMap<String, ArmedTimer>,
+    // State of each item's regions by (label, region), once it left the initial one
+    region_states: HashMap<(String, String), String>,
+    // Last result of each guard probe command, and when it ran. Probes run
+    // elsewhere, see guard_probes and record_probe; guards only read this.
+    probe_results: HashMap<String, (Instant, bool)>,
 }
 
+// How long a probe result is trusted. Callers run due probes more often than
+// this, so a result only goes stale when probing stopped.
+pub const PROBE_CACHE_TIME: Duration = Duration::from_secs(15);
+
 impl ButtonManager {
     pub fn new() -> Self {
         ButtonManager {
             button_states: HashMap::new(),
             button_colors: HashMap::new(),
             menu_config: None,
+            pending_hooks: Vec::new(),
+            timers: HashMap::new(),
+            region_states: HashMap::new(),
+            probe_results: HashMap::new(),
         }
     }
 
@@ -86,58 +1058,577 @@ impl ButtonManager {
 
     pub fn from_menu_config(config: MenuConfig) -> Self {
         let mut manager = ButtonManager::new();
-        manager.menu_config = Some(config.clone());
         
         // Initialize button states based on the menu config
         for item in &config.menu_items {
-            for (_state_name, state) in &item.state_machine.states {
-                for (action, _next_state) in &state.transitions {
-                    let key = Self::make_key(&item.label, action);
-                    manager.button_states.insert(key.clone(), item.state_machine.initial_state.clone());
-                    
-                    // Set default color if defined in the state style
-                    if let Some(initial_state) = item.state_machine.states.get(&item.state_machine.initial_state) {
-                        if let Some(color) = initial_state.style.get("color") {
-                            manager.button_colors.insert(key.clone(), color.clone());
-                        } else {
-                            manager.button_colors.insert(key.clone(), "#007BFF".to_string());
-                        }
+            manager.init_item_states(item);
+        }
+        
+        let labels: Vec<String> = config.menu_items.iter().map(|item| item.label.clone()).collect();
+        manager.menu_config = Some(config);
+        for label in labels {
+            manager.arm_timer(&label);
+        }
+        manager
+    }
+
+    // Swap in a changed config. Items keep their state when the item and the
+    // state still exist, and a running timer keeps its time left when its
+    // state's `after:` did not change.
+    pub fn reload_config(&mut self, config: MenuConfig) {
+        let states: Vec<(String, String)> = config.menu_items.iter()
+            .filter_map(|item| Some((item.label.clone(), self.current_state(&item.label)?)))
+            .collect();
+        let mut timers = std::mem::take(&mut self.timers);
+
+        let mut reloaded = ButtonManager::from_menu_config(config);
+        for (label, state) in states {
+            let Some(item) = reloaded.find_item(&label) else { continue };
+            if !item.state_machine.states.contains_key(&state) {
+                continue;
+            }
+            let timer_after = item.state_machine.timer_state(&state)
+                .map(|(timer_state, after)| (timer_state.to_string(), after.clone()));
+            reloaded.set_item_state(&label, &state);
+
+            let unchanged = |timer: &ArmedTimer| {
+                timer_after.as_ref().is_some_and(|(timer_state, after)| {
+                    *timer_state == timer.state
+                        && after.action == timer.action
+                        && parse_duration(&after.duration).ok() == Some(timer.duration)
+                })
+            };
+            if let Some(timer) = timers.remove(&label).filter(unchanged) {
+                reloaded.timers.insert(label, timer);
+            }
+        }
+
+        for ((label, region), state) in std::mem::take(&mut self.region_states) {
+            let exists = reloaded.find_item(&label)
+                .and_then(|item| item.state_machine.regions.get(&region))
+                .is_some_and(|r| r.machine.states.contains_key(&state));
+            if exists {
+                reloaded.region_states.insert((label, region), state);
+            }
+        }
+
+        reloaded.pending_hooks = std::mem::take(&mut self.pending_hooks);
+        *self = reloaded;
+    }
+
+    // Add a menu item at runtime, e.g. a newly created profile
+    pub fn add_menu_item(&mut self, item: MenuItemConfig) {
+        self.init_item_states(&item);
+        let label = item.label.clone();
+        self.menu_config
+            .get_or_insert_with(MenuConfig::default)
+            .menu_items
+            .push(item);
+        self.arm_timer(&label);
+    }
+
+    // Start the timer of the item's current state, or of the nearest state
+    // it is nested in that has one, replacing any other
+    fn arm_timer(&mut self, label: &str) {
+        self.timers.remove(label);
+        let Some(state) = self.current_state(label) else { return };
+        let Some((timer_state, after)) = self.find_item(label)
+            .and_then(|item| item.state_machine.timer_state(&state))
+            .map(|(timer_state, after)| (timer_state.to_string(), after.clone()))
+        else {
+            return;
+        };
+        let Ok(duration) = parse_duration(&after.duration) else { return };
+
+        self.timers.insert(label.to_string(), ArmedTimer {
+            state: timer_state,
+            action: after.action,
+            duration,
+            due: Instant::now() + duration,
+        });
+    }
+
+    // Timed actions that are due, for the caller to press and run like a
+    // click. Ones whose guards do not hold yet wait another period.
+    pub fn take_due_timers(&mut self) -> Vec<SessionStep> {
+        let now = Instant::now();
+        let due: Vec<String> = self.timers.iter()
+            .filter(|(_, timer)| timer.due <= now)
+            .map(|(label, _)| label.clone())
+            .collect();
+
+        let mut steps = Vec::new();
+        for label in due {
+            let Some(timer) = self.timers.get(&label).cloned() else { continue };
+            if !self.is_in_state(&label, &timer.state) {
+                self.timers.remove(&label);
+                continue;
+            }
+
+            if let Some(reason) = self.blocked_reason(&label, &timer.action) {
+                println!("Timed {} of {} waits: {}", timer.action, label, reason);
+                if let Some(timer) = self.timers.get_mut(&label) {
+                    timer.due = now + timer.duration;
+                }
+                continue;
+            }
+
+            self.timers.remove(&label);
+            steps.push(SessionStep { label, action: timer.action, delay_secs: 0 });
+        }
+        steps
+    }
+
+    // Time left on the item's timer, e.g. "kill in 1:59:30"
+    pub fn countdown(&self, label: &str) -> Option<String> {
+        let timer = self.timers.get(label)?;
+        // Rounded up, so the countdown reaches 0:00 when the action is due
+        let left = timer.due.saturating_duration_since(Instant::now()).as_millis().div_ceil(1000) as u64;
+        let (hours, minutes, seconds) = (left / 3600, left / 60 % 60, left % 60);
+
+        Some(if hours > 0 {
+            format!("{} in {}:{:02}:{:02}", timer.action, hours, minutes, seconds)
+        } else {
+            format!("{} in {}:{:02}", timer.action, minutes, seconds)
+        })
+    }
+
+    fn init_item_states(&mut self, item: &MenuItemConfig) {
+        let initial = item.state_machine.initial();
+        for state in item.state_machine.states.values() {
+            for action in state.transitions.keys() {
+                let key = Self::make_key(&item.label, action);
+                self.button_states.insert(key.clone(), initial.clone());
+                
+                // Set default color if defined in the state style
+                if let Some(initial_state) = item.state_machine.states.get(&initial) {
+                    if let Some(color) = initial_state.style.get("color") {
+                        self.button_colors.insert(key.clone(), color.clone());
+                    } else {
+                        self.button_colors.insert(key.clone(), "#007BFF".to_string());
                     }
                 }
             }
         }
+    }
+
+    // Move the item along the action's transition from its current state,
+    // or move the region the action belongs to, returning the transition
+    // taken. None when no active state has such an action.
+    pub fn press_button(&mut self, profile: &str, action: &str) -> Option<Transition> {
+        // Find the transition for this action
+        let (region, current_state, transition) = self.item_transition(profile, action)?;
+        let next_state = self.machine(profile, region.as_deref())?.resolve(&transition.to);
+
+        self.queue_hooks(profile, region.as_deref(), &current_state, &next_state, action);
+        match region {
+            None => {
+                println!("Button state changed: {} -> {}", current_state, next_state);
+                self.set_item_state(profile, &next_state);
+            }
+            Some(region) => {
+                println!("Region {} of {} changed: {} -> {}", region, profile, current_state, next_state);
+                self.region_states.insert((profile.to_string(), region), next_state);
+            }
+        }
+        Some(transition)
+    }
+
+    // The transition `action` takes on the item: from the main machine's
+    // current state, or from an active region's. Also returns the region and
+    // the state the transition starts from.
+    fn item_transition(&self, label: &str, action: &str) -> Option<(Option<String>, String, Transition)> {
+        let item = self.find_item(label)?;
+        let current = self.current_state(label)?;
+        if let Some(transition) = item.state_machine.transition(&current, action) {
+            return Some((None, current, transition.clone()));
+        }
+
+        item.state_machine.regions.iter().find_map(|(name, region)| {
+            let state = self.region_state(label, name)?;
+            let transition = region.machine.transition(&state, action)?.clone();
+            Some((Some(name.clone()), state, transition))
+        })
+    }
+
+    // The item's main state machine, or one of its regions
+    fn machine(&self, label: &str, region: Option<&str>) -> Option<&StateMachine> {
+        let machine = &self.find_item(label)?.state_machine;
+        match region {
+            None => Some(machine),
+            Some(region) => machine.regions.get(region).map(|region| &region.machine),
+        }
+    }
+
+    // The state of one of the item's regions; None while it is not active
+    pub fn region_state(&self, label: &str, region: &str) -> Option<String> {
+        let item = self.find_item(label)?;
+        let definition = item.state_machine.regions.get(region)?;
+        if let Some(within) = &definition.within {
+            if !self.is_in_state(label, within) {
+                return None;
+            }
+        }
+
+        let key = (label.to_string(), region.to_string());
+        Some(self.region_states.get(&key).cloned().unwrap_or_else(|| definition.machine.initial()))
+    }
+
+    // Every item's state and the state of its active regions, to save
+    pub fn saved_states(&self) -> SavedStates {
+        let Some(config) = &self.menu_config else { return SavedStates::new() };
+        config.menu_items.iter()
+            .filter_map(|item| {
+                let state = self.current_state(&item.label)?;
+                let regions = item.state_machine.regions.keys()
+                    .filter_map(|name| Some((name.clone(), self.region_state(&item.label, name)?)))
+                    .collect();
+                Some((item.label.clone(), SavedState { state, regions }))
+            })
+            .collect()
+    }
+
+    // Whether the item is in `state` or a state nested in it
+    pub fn is_in_state(&self, label: &str, state: &str) -> bool {
+        let (Some(item), Some(current)) = (self.find_item(label), self.current_state(label)) else {
+            return false;
+        };
+        item.state_machine.is_in(&current, state)
+    }
+
+    // Queue on_exit commands of the states left and on_enter commands of
+    // the states entered. Staying in the same state, like a snapshot does,
+    // runs neither.
+    fn queue_hooks(&mut self, label: &str, region: Option<&str>, from: &str, to: &str, action: &str) {
+        if from == to {
+            return;
+        }
+        let Some(machine) = self.machine(label, region) else { return };
+
+        let hooks: Vec<Hook> = machine.hooks_between(from, to)
+            .into_iter()
+            .map(|command| Hook { label: label.to_string(), command: command.replace("{ACTION}", action) })
+            .collect();
+        self.pending_hooks.extend(hooks);
+    }
+
+    // The hooks queued by state changes so far, for the caller to run
+    pub fn take_hooks(&mut self) -> Vec<Hook> {
+        std::mem::take(&mut self.pending_hooks)
+    }
+
+    // Labels of the selected items whose current state allows `action`.
+    // Guards and prerequisites are left to each item's plan_action.
+    pub fn bulk_targets(&self, action: &str, selection: &Selection) -> Vec<String> {
+        let Some(config) = &self.menu_config else { return Vec::new() };
         
-        manager
+        config.menu_items.iter()
+            .filter(|item| selection.matches(item))
+            .filter(|item| self.get_available_actions(&item.label).iter().any(|a| a == action))
+            .map(|item| item.label.clone())
+            .collect()
     }
 
-    pub fn press_button(&mut self, profile: &str, action: &str) {
-        let key = Self::make_key(profile, action);
+    // Aggregate state of a session's items, e.g. "3 started, 2 default"
+    pub fn session_status(&self, name: &str) -> Option<String> {
+        let session = self.menu_config.as_ref()?.sessions.get(name)?;
         
-        if let Some(config) = &self.menu_config {
-            for item in &config.menu_items {
-                if item.label == profile {
-                    // Get the current state for this button
-                    let current_state = self.button_states.get(&key).unwrap_or(&item.state_machine.initial_state).clone();
-                    
-                    // Find the transition for this action
-                    if let Some(state) = item.state_machine.states.get(&current_state) {
-                        if let Some(next_state) = state.transitions.get(action) {
-                            // Update the button state
-                            println!("Button state changed: {} -> {}", current_state, next_state);
-                            self.button_states.insert(key.clone(), next_state.clone());
-                            
-                            // Update button color if specified in the state style
-                            if let Some(next_state_def) = item.state_machine.states.get(next_state) {
-                                if let Some(color) = next_state_def.style.get("color") {
-                                    self.button_colors.insert(key.clone(), color.clone());
-                                }
-                            }
-                        }
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for item in &session.items {
+            let state = self.current_state(&item.label).unwrap_or_else(|| "missing".to_string());
+            *counts.entry(state).or_default() += 1;
+        }
+        
+        let parts: Vec<String> = counts.iter().map(|(state, count)| format!("{} {}", count, state)).collect();
+        Some(parts.join(", "))
+    }
+
+    // The actions that take an item from its current state to `target`, by
+    // the fewest transitions. Preferred actions win between equally short paths.
+    pub fn path_to_state(&self, label: &str, target: &str, preferred: &[&str]) -> Option<Vec<String>> {
+        let item = self.find_item(label)?;
+        let start = self.current_state(label)?;
+
+        let machine = &item.state_machine;
+        let mut queue = std::collections::VecDeque::from([(start.clone(), Vec::new())]);
+        let mut seen = vec![start];
+        while let Some((state, path)) = queue.pop_front() {
+            if machine.is_in(&state, target) {
+                return Some(path);
+            }
+
+            let mut actions = machine.actions(&state);
+            actions.sort_by_key(|action| !preferred.contains(&action.as_str()));
+            for action in actions {
+                let Some(transition) = machine.transition(&state, &action) else { continue };
+                let next = machine.resolve(&transition.to);
+                if !seen.contains(&next) {
+                    seen.push(next.clone());
+                    let mut next_path = path.clone();
+                    next_path.push(action);
+                    queue.push_back((next, next_path));
+                }
+            }
+        }
+        None
+    }
+
+    // Everything to press, in order, for `action` on an item: first the
+    // prerequisites with `auto_start` that are not up yet (and their own
+    // prerequisites), then the action itself. Fails when a prerequisite is
+    // not up and may not be started automatically.
+    pub fn plan_action(&self, label: &str, action: &str) -> Result<Vec<SessionStep>, String> {
+        let mut steps = Vec::new();
+        self.plan_into(label, action, &mut steps)?;
+        Ok(steps)
+    }
+
+    fn plan_into(&self, label: &str, action: &str, steps: &mut Vec<SessionStep>) -> Result<(), String> {
+        let item = self.find_item(label).ok_or_else(|| format!("No menu item named {}", label))?;
+        let found = self.item_transition(label, action);
+        let transition = found.as_ref().map(|(_, _, transition)| transition);
+
+        // Only actions of the main machine that bring the item up need its prerequisites
+        let machine = &item.state_machine;
+        let brings_up = found.as_ref().is_some_and(|(region, _, transition)| {
+            region.is_none()
+                && item.state_for_event("start").is_some_and(|up| machine.is_in(&machine.resolve(&transition.to), &up))
+        });
+        if brings_up {
+            for requirement in &item.requires {
+                if self.is_in_state(&requirement.label, &requirement.state)
+                    || steps.iter().any(|step| step.label == requirement.label)
+                {
+                    continue;
+                }
+                if !requirement.auto_start {
+                    return Err(format!("{} needs {} to be {} first", label, requirement.label, requirement.state));
+                }
+
+                let path = self.path_to_state(&requirement.label, &requirement.state, &["start"])
+                    .ok_or_else(|| format!("{} cannot get from its current state to {}", requirement.label, requirement.state))?;
+                for (i, required_action) in path.iter().enumerate() {
+                    if i == 0 {
+                        self.plan_into(&requirement.label, required_action, steps)?;
+                    } else {
+                        steps.push(SessionStep { label: requirement.label.clone(), action: required_action.clone(), delay_secs: 0 });
                     }
-                    break;
                 }
             }
         }
+
+        // Guards see the prerequisites as the plan leaves them
+        for guard in transition.map(|transition| transition.guards.as_slice()).unwrap_or_default() {
+            self.check_guard(guard, steps)
+                .map_err(|reason| format!("Cannot {} {}: {}", action, label, reason))?;
+        }
+
+        steps.push(SessionStep { label: label.to_string(), action: action.to_string(), delay_secs: 0 });
+        Ok(())
+    }
+
+    // Why `action` cannot run on the item right now: the first of its
+    // transition's guards that does not hold. None when nothing stops it.
+    pub fn blocked_reason(&self, label: &str, action: &str) -> Option<String> {
+        let (_, _, transition) = self.item_transition(label, action)?;
+
+        transition.guards.iter().find_map(|guard| self.check_guard(guard, &[]).err())
+    }
+
+    // `planned` are steps that run before the guarded action, so an item
+    // state guard looks at the state they leave that item in
+    fn check_guard(&self, guard: &Guard, planned: &[SessionStep]) -> Result<(), String> {
+        match guard {
+            Guard::FileExists { file_exists } => {
+                if std::path::Path::new(file_exists).exists() {
+                    Ok(())
+                } else {
+                    Err(format!("{} does not exist", file_exists))
+                }
+            }
+            Guard::Env { env } => match std::env::var(env) {
+                Ok(value) if !value.is_empty() => Ok(()),
+                _ => Err(format!("${} is not set", env)),
+            },
+            Guard::Probe { probe } => match self.probe_result(probe) {
+                Some(true) => Ok(()),
+                Some(false) => Err(format!("probe failed: {}", probe)),
+                None => Err(format!("probe not checked yet: {}", probe)),
+            },
+            Guard::ItemState { item, state } => {
+                let current = self.planned_state(item, planned);
+                let holds = current.as_ref()
+                    .zip(self.find_item(item))
+                    .is_some_and(|(current, found)| found.state_machine.is_in(current, state));
+                if holds {
+                    Ok(())
+                } else {
+                    Err(format!("{} is {}, not {}", item, current.as_deref().unwrap_or("missing"), state))
+                }
+            }
+        }
+    }
+
+    // The state an item is in once the planned steps have run
+    fn planned_state(&self, label: &str, planned: &[SessionStep]) -> Option<String> {
+        let item = self.find_item(label)?;
+        let mut state = self.current_state(label)?;
+        for step in planned.iter().filter(|step| step.label == label) {
+            if let Some(transition) = item.state_machine.transition(&state, &step.action) {
+                state = item.state_machine.resolve(&transition.to);
+            }
+        }
+        Some(state)
+    }
+
+    // The cached result of a probe; None until it ran, or once it is stale
+    fn probe_result(&self, probe: &str) -> Option<bool> {
+        self.probe_results.get(probe)
+            .filter(|(checked, _)| checked.elapsed() < PROBE_CACHE_TIME)
+            .map(|(_, success)| *success)
+    }
+
+    // Every probe command of any guard, to run again and record
+    pub fn guard_probes(&self) -> Vec<String> {
+        let Some(config) = &self.menu_config else { return Vec::new() };
+        let mut probes: Vec<String> = config.menu_items.iter()
+            .flat_map(|item| item.state_machine.all_transitions())
+            .flat_map(|transition| &transition.guards)
+            .filter_map(|guard| match guard {
+                Guard::Probe { probe } => Some(probe.clone()),
+                _ => None,
+            })
+            .collect();
+        probes.sort();
+        probes.dedup();
+        probes
+    }
+
+    pub fn record_probe(&mut self, probe: &str, success: bool) {
+        self.probe_results.insert(probe.to_string(), (Instant::now(), success));
+    }
+
+    // Items that require `label` in a state it is no longer in, and are up,
+    // with the actions that bring them down: dependents of dependents first
+    pub fn dependents_to_stop(&self, label: &str) -> Vec<SessionStep> {
+        let mut steps = Vec::new();
+        let mut visited = vec![label.to_string()];
+        self.collect_dependents(label, None, &mut visited, &mut steps);
+        steps
+    }
+
+    // `assumed` is the state `label` is about to be put in, if it is not there yet
+    fn collect_dependents(&self, label: &str, assumed: Option<&str>, visited: &mut Vec<String>, steps: &mut Vec<SessionStep>) {
+        let Some(config) = &self.menu_config else { return };
+        let Some(required) = self.find_item(label) else { return };
+        let state = assumed.map(|state| required.state_machine.resolve(state))
+            .or_else(|| self.current_state(label))
+            .unwrap_or_default();
+
+        for item in &config.menu_items {
+            if visited.contains(&item.label) {
+                continue;
+            }
+            let broken = item.requires.iter()
+                .any(|requirement| requirement.label == label && !required.state_machine.is_in(&state, &requirement.state));
+            let up = item.state_for_event("start").is_some_and(|up| self.is_in_state(&item.label, &up));
+            if !broken || !up {
+                continue;
+            }
+
+            let Some(down) = item.state_for_event("die") else { continue };
+            let Some(path) = self.path_to_state(&item.label, &down, &["stop", "kill"]) else { continue };
+
+            visited.push(item.label.clone());
+            self.collect_dependents(&item.label, Some(&down), visited, steps);
+            steps.extend(path.into_iter().map(|action| SessionStep { label: item.label.clone(), action, delay_secs: 0 }));
+        }
+    }
+
+    pub fn find_item(&self, profile: &str) -> Option<&MenuItemConfig> {
+        self.menu_config.as_ref()?.menu_items.iter().find(|item| item.label == profile)
+    }
+
+    // The state a menu item is in; all buttons of an item share it
+    pub fn current_state(&self, profile: &str) -> Option<String> {
+        let item = self.find_item(profile)?;
+        
+        let state = item.state_machine.states.values()
+            .flat_map(|state| state.transitions.keys())
+            .find_map(|action| self.button_states.get(&Self::make_key(profile, action)))
+            .cloned()
+            .unwrap_or_else(|| item.state_machine.initial());
+        
+        Some(state)
+    }
+
+    // Move every button of a menu item to `state_name`, updating colors from the state style
+    pub fn set_item_state(&mut self, profile: &str, state_name: &str) {
+        let Some(item) = self.find_item(profile) else { return };
+        let previous = self.current_state(profile);
+        
+        let color = item.state_machine.states.get(state_name)
+            .and_then(|state| state.style.get("color"))
+            .cloned();
+        let actions: Vec<String> = item.state_machine.states.values()
+            .flat_map(|state| state.transitions.keys().cloned())
+            .collect();
+        let timer_state = item.state_machine.timer_state(state_name).map(|(timer_state, _)| timer_state.to_string());
+        // Regions only active within a state start over once it is left
+        let inactive_regions: Vec<(String, String)> = item.state_machine.regions.iter()
+            .filter(|(_, region)| region.within.as_ref().is_some_and(|within| !item.state_machine.is_in(state_name, within)))
+            .map(|(name, _)| (profile.to_string(), name.clone()))
+            .collect();
+        
+        for action in actions {
+            let key = Self::make_key(profile, &action);
+            self.button_states.insert(key.clone(), state_name.to_string());
+            if let Some(color) = &color {
+                self.button_colors.insert(key, color.clone());
+            }
+        }
+        for key in inactive_regions {
+            self.region_states.remove(&key);
+        }
+
+        // A timer runs from entering its state; staying there, or moving
+        // between states nested in it, keeps the time left
+        let running = self.timers.get(profile).map(|timer| timer.state.clone());
+        if previous.as_deref() != Some(state_name) && (timer_state.is_none() || timer_state != running) {
+            self.arm_timer(profile);
+        }
+    }
+
+    // Apply a container runtime event (start, pause, unpause, die) to the menu
+    // item whose container it is. Returns the item label when its state changed.
+    pub fn apply_container_event(&mut self, container: &str, event: &str) -> Option<String> {
+        let label = self.menu_config.as_ref()?
+            .menu_items
+            .iter()
+            .find(|item| item.container_name().as_deref() == Some(container))?
+            .label
+            .clone();
+        
+        println!("Container {} reported '{}'", container, event);
+        self.apply_item_event(&label, event).then_some(label)
+    }
+
+    // Move a menu item to the state an event maps to. Returns whether its state changed.
+    pub fn apply_item_event(&mut self, label: &str, event: &str) -> bool {
+        let Some(item) = self.find_item(label) else { return false };
+        let Some(event_state) = item.state_for_event(event) else { return false };
+        let next_state = item.state_machine.resolve(&event_state);
+        
+        // Already there, possibly in a state nested in it
+        if self.is_in_state(label, &event_state) {
+            return false;
+        }
+        
+        let current_state = self.current_state(label).unwrap_or_default();
+        println!("Event '{}': {} {} -> {}", event, label, current_state, next_state);
+        self.queue_hooks(label, None, &current_state, &next_state, event);
+        self.set_item_state(label, &next_state);
+        true
     }
 
     // Corrected get_action_color method that uses existing fields
@@ -191,29 +1682,309 @@ impl ButtonManager {
 
     // Add this new method
     pub fn get_available_actions(&self, profile: &str) -> Vec<String> {
-        if let Some(config) = &self.menu_config {
-            // Find the menu item for this profile
-            for item in &config.menu_items {
-                if item.label == profile {
-                    // First find the current state for any button in this profile
-                    let mut current_state = item.state_machine.initial_state.clone();
-                    
-                    // Look for any button from this profile to get its current state
-                    for (key, state) in &self.button_states {
-                        if key.starts_with(&format!("{}:", profile)) {
-                            current_state = state.clone();
-                            break;
-                        }
-                    }
-                    
-                    // Now get transitions available from this state
-                    if let Some(state) = item.state_machine.states.get(&current_state) {
-                        return state.transitions.keys().cloned().collect();
-                    }
-                }
+        let Some(current_state) = self.current_state(profile) else { return Vec::new() };
+        let Some(item) = self.find_item(profile) else { return Vec::new() };
+        
+        // Now get transitions available from this state, the states it is
+        // nested in and the active regions
+        let mut actions = item.state_machine.actions(&current_state);
+        for name in item.state_machine.regions.keys() {
+            if let (Some(state), Some(region)) = (self.region_state(profile, name), item.state_machine.regions.get(name)) {
+                actions.extend(region.machine.actions(&state));
             }
         }
-        
-        Vec::new() // No transitions found
+        actions
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> MenuConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn standard_machine_kills_gracefully_or_by_force() {
+        let machine = StateMachine::standard();
+
+        for state in ["started", "frozen"] {
+            let actions = machine.actions(state);
+            assert!(actions.contains(&"kill".to_string()), "{} has no kill", state);
+            assert!(actions.contains(&"force_kill".to_string()), "{} has no force_kill", state);
+            assert!(!actions.contains(&"stop".to_string()), "{} still has stop", state);
+            assert!(!actions.contains(&"snapshot".to_string()), "{} has snapshot", state);
+            assert_eq!(machine.transition(state, "kill").unwrap().to, "killed");
+            assert_eq!(machine.transition(state, "force_kill").unwrap().to, "killed");
+        }
+        assert!(machine.validate().is_ok());
+
+        // kill runs the graceful stop, force_kill the kill
+        let item = MenuItemConfig::new("work", "sys {ACTION} firefox work");
+        let kill = machine.transition("started", "kill");
+        assert_eq!(item.action_command("kill", kill).command, "sys stop firefox work");
+        let force_kill = machine.transition("started", "force_kill");
+        assert_eq!(item.action_command("force_kill", force_kill).command, "sys force_kill firefox work");
+    }
+
+    #[test]
+    fn transitions_running_another_action_keep_it_when_written() {
+        let config = config(
+            "menu_items:
+- label: work
+  command_template: echo
+  state_machine:
+    initial_state: up
+    states:
+      up:
+        transitions:
+          kill:
+            to: up
+            action: stop
+",
+        );
+        let kill = config.menu_items[0].state_machine.transition("up", "kill").unwrap();
+        assert_eq!(kill.runs("kill"), "stop");
+        assert!(serde_yaml::to_string(&config).unwrap().contains("action: stop"));
+    }
+
+    #[test]
+    fn items_without_a_machine_get_the_standard_one() {
+        let config = config("menu_items:\n- label: work\n  command_template: echo {ACTION}\n");
+
+        assert!(config.menu_items[0].state_machine.is_standard());
+        // and it is left out again when written back
+        let written = serde_yaml::to_string(&config).unwrap();
+        assert!(!written.contains("state_machine"), "{}", written);
+    }
+
+    #[test]
+    fn backend_flags_come_from_the_template_or_the_item() {
+        let mut item = MenuItemConfig::new("work", "sys {ACTION} firefox work --docker-socket /run/docker.sock --backend api");
+        assert_eq!(item.backend_flags(), ["--backend", "api", "--docker-socket", "/run/docker.sock"]);
+
+        // the template's flags come first on the command line, so they win
+        item.backend = Some("native".to_string());
+        assert_eq!(item.backend_flags()[..2], ["--backend", "api"]);
+
+        let mut item = MenuItemConfig::new("work", "sys {ACTION} firefox work");
+        assert!(item.backend_flags().is_empty());
+        item.backend = Some("native".to_string());
+        assert_eq!(item.backend_flags(), ["--backend", "native"]);
+    }
+
+    fn guarded(guards: &str) -> ButtonManager {
+        ButtonManager::from_menu_config(config(&format!(
+            "menu_items:
+- label: vpn
+  command_template: echo
+- label: mail
+  command_template: echo
+  state_machine:
+    initial_state: down
+    states:
+      down:
+        transitions:
+          start:
+            to: up
+            guards:
+{}
+      up:
+        transitions:
+          kill: down
+",
+            guards
+        )))
+    }
+
+    #[test]
+    fn env_and_file_guards_check_the_system() {
+        let manager = guarded("            - env: PATH\n            - file_exists: /\n");
+        assert_eq!(manager.blocked_reason("mail", "start"), None);
+
+        let manager = guarded("            - env: MENU_RUNNER_TEST_UNSET\n");
+        assert_eq!(manager.blocked_reason("mail", "start").as_deref(), Some("$MENU_RUNNER_TEST_UNSET is not set"));
+
+        let manager = guarded("            - file_exists: /nonexistent/menu-runner\n");
+        assert_eq!(manager.blocked_reason("mail", "start").as_deref(), Some("/nonexistent/menu-runner does not exist"));
+    }
+
+    #[test]
+    fn probe_guards_use_recent_results_only() {
+        let mut manager = guarded("            - probe: ping -c1 vpn\n");
+        assert_eq!(manager.guard_probes(), ["ping -c1 vpn"]);
+        assert_eq!(manager.blocked_reason("mail", "start").as_deref(), Some("probe not checked yet: ping -c1 vpn"));
+
+        manager.record_probe("ping -c1 vpn", false);
+        assert_eq!(manager.blocked_reason("mail", "start").as_deref(), Some("probe failed: ping -c1 vpn"));
+
+        manager.record_probe("ping -c1 vpn", true);
+        assert_eq!(manager.blocked_reason("mail", "start"), None);
+
+        // a result older than the cache time counts as not checked
+        if let Some(stale) = Instant::now().checked_sub(PROBE_CACHE_TIME + Duration::from_secs(1)) {
+            manager.probe_results.insert("ping -c1 vpn".to_string(), (stale, true));
+            assert_eq!(manager.blocked_reason("mail", "start").as_deref(), Some("probe not checked yet: ping -c1 vpn"));
+        }
+    }
+
+    #[test]
+    fn item_state_guards_follow_the_other_item() {
+        let mut manager = guarded("            - item: vpn\n              state: started\n");
+        assert_eq!(manager.blocked_reason("mail", "start").as_deref(), Some("vpn is default, not started"));
+
+        manager.press_button("vpn", "start");
+        assert_eq!(manager.blocked_reason("mail", "start"), None);
+    }
+
+    const NESTED: &str = "menu_items:
+- label: work
+  command_template: echo
+  state_machine:
+    initial_state: down
+    states:
+      down:
+        transitions:
+          start: up
+      up:
+        initial: running
+        transitions:
+          kill: down
+      running:
+        parent: up
+        transitions:
+          freeze: frozen
+      frozen:
+        parent: up
+        transitions:
+          unfreeze: running
+    regions:
+      audio:
+        within: up
+        initial_state: loud
+        states:
+          loud:
+            transitions:
+              mute: muted
+          muted:
+            transitions:
+              unmute: loud
+";
+
+    #[test]
+    fn nested_states_offer_their_parents_actions_and_active_regions() {
+        let mut manager = ButtonManager::from_menu_config(config(NESTED));
+        assert_eq!(manager.get_available_actions("work"), ["start"]);
+
+        // entering up ends up in its initial state, with the region active
+        manager.press_button("work", "start");
+        assert_eq!(manager.current_state("work").as_deref(), Some("running"));
+        assert_eq!(manager.get_available_actions("work"), ["freeze", "kill", "mute"]);
+
+        manager.press_button("work", "mute");
+        manager.press_button("work", "freeze");
+        assert_eq!(manager.get_available_actions("work"), ["kill", "unfreeze", "unmute"]);
+
+        // leaving up resets the region
+        manager.press_button("work", "kill");
+        assert_eq!(manager.get_available_actions("work"), ["start"]);
+        assert_eq!(manager.region_state("work", "audio"), None);
+        manager.press_button("work", "start");
+        assert_eq!(manager.region_state("work", "audio").as_deref(), Some("loud"));
+    }
+
+    #[test]
+    fn saved_states_hold_active_regions_only() {
+        let mut manager = ButtonManager::from_menu_config(config(NESTED));
+        assert_eq!(manager.saved_states()["work"], SavedState { state: "down".to_string(), regions: BTreeMap::new() });
+
+        manager.press_button("work", "start");
+        manager.press_button("work", "mute");
+        assert_eq!(
+            manager.saved_states()["work"],
+            SavedState { state: "running".to_string(), regions: BTreeMap::from([("audio".to_string(), "muted".to_string())]) },
+        );
+    }
+
+    #[test]
+    fn consistent_nesting_validates() {
+        assert_eq!(config(NESTED).validate_state_machines(), Ok(()));
+    }
+
+    #[test]
+    fn inconsistent_nesting_is_rejected() {
+        let broken = [
+            ("parent: up", "parent: nowhere", "state running is nested in unknown state nowhere"),
+            ("initial: running", "initial: down", "initial state down of up is not nested in it"),
+            ("kill: down\n      running:", "kill: down\n        parent: frozen\n      running:", "is nested in itself"),
+            ("initial_state: down", "initial_state: gone", "unknown initial state gone"),
+            ("within: up", "within: nowhere", "region audio is within unknown state nowhere"),
+            ("mute: muted", "freeze: muted", "region audio uses action freeze"),
+        ];
+
+        for (from, to, expected) in broken {
+            assert!(NESTED.contains(from), "{}", from);
+            let error = config(&NESTED.replacen(from, to, 1)).validate_state_machines().unwrap_err();
+            assert!(error.starts_with("work: ") && error.contains(expected), "{} gave {}", to, error);
+        }
+    }
+
+    #[test]
+    fn durations_add_up_their_parts() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172_800)));
+        assert_eq!(parse_duration(" 90 "), Ok(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn malformed_durations_are_rejected() {
+        for text in ["", "m", "5x", "1.5h", "-3s"] {
+            assert!(parse_duration(text).is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn overflowing_durations_are_rejected() {
+        assert!(parse_duration("18446744073709551615d").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+        assert!(parse_duration("18446744073709551615s1").is_err());
+    }
+
+    #[test]
+    fn reload_keeps_the_state_of_items_that_still_have_it() {
+        let mut manager = ButtonManager::from_menu_config(config(
+            "menu_items:\n- label: a\n  command_template: echo\n- label: b\n  command_template: echo\n",
+        ));
+        manager.press_button("a", "start");
+        manager.press_button("b", "start");
+        manager.press_button("b", "freeze");
+
+        // b no longer has a frozen state, c is new
+        manager.reload_config(config(
+            "menu_items:
+- label: a
+  command_template: echo
+- label: b
+  command_template: echo
+  state_machine:
+    initial_state: down
+    states:
+      down:
+        transitions:
+          start: started
+      started:
+        transitions:
+          kill: down
+- label: c
+  command_template: echo
+",
+        ));
+
+        assert_eq!(manager.current_state("a").as_deref(), Some("started"));
+        assert_eq!(manager.current_state("b").as_deref(), Some("down"));
+        assert_eq!(manager.current_state("c").as_deref(), Some("default"));
+    }
+}
@@ -1,9 +1,9 @@
-use std::collections::HashMap;
 use std::path::Path;
 use std::io;
 use std::fmt;
-use crate::models::{CommandInfo, GroupedMenuEntry, SlintMenuEntry};
-use crate::models::{ButtonManager, MenuConfig};
+use crate::models::SlintMenuEntry;
+use crate::layers::{load_layered_config, read_config_file, PROJECT_CONFIG};
+use crate::models::{ButtonManager, MenuConfig, MenuItemConfig};
 use tokio::fs as tokio_fs;
 
 // Custom error type for menu loading operations
@@ -12,6 +12,7 @@ pub enum MenuError {
     IoError(io::Error),
     ParseError(String),
     FileNotFound(String),
+    InvalidConfig(String),
 }
 
 impl fmt::Display for MenuError {
@@ -20,6 +21,7 @@ impl fmt::Display for MenuError {
             MenuError::IoError(err) => write!(f, "I/O error: {}", err),
             MenuError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             MenuError::FileNotFound(path) => write!(f, "File not found: {}", path),
+            MenuError::InvalidConfig(msg) => write!(f, "Invalid config: {}", msg),
         }
     }
 }
@@ -44,248 +46,39 @@ impl From<serde_yaml::Error> for MenuError {
 
 impl std::error::Error for MenuError {}
 
-pub async fn load_menu_async() -> Result<Vec<CommandInfo>, MenuError> {
-    let menu_path = Path::new("configs/future_menu.txt");
-
-    if !menu_path.exists() {
-        return Err(MenuError::FileNotFound("configs/future_menu.txt".to_string()));
-    }
-
-    let content = tokio_fs::read_to_string(menu_path).await?;
-    Ok(parse_future_menu_format(&content))
-}
-
-pub async fn load_menu_json_async() -> Result<Vec<CommandInfo>, MenuError> {
-    let menu_path = Path::new("configs/future_menu.json");
-
-    if !menu_path.exists() {
-        return Err(MenuError::FileNotFound("configs/future_menu.json".to_string()));
-    }
-
-    let content = tokio_fs::read_to_string(menu_path).await?;
-    let commands = serde_json::from_str::<Vec<CommandInfo>>(&content)?;
-    Ok(commands)
-}
-
-fn parse_future_menu_format(content: &str) -> Vec<CommandInfo> {
-    let mut result = Vec::new();
-    let lines: Vec<&str> = content.lines().collect();
-    let mut i = 0;
-
-    while i < lines.len() {
-        let line = lines[i].trim();
-        if !line.is_empty() && !line.starts_with('#') {
-            if let Some(command_info) = extract_command_info(line) {
-                result.push(command_info);
-            }
-        }
-        i += 1;
-    }
-
-    result
-}
-
-pub async fn load_menu_yaml_async() -> Result<Vec<CommandInfo>, MenuError> {
-    let menu_path = Path::new("configs/menu_config.yaml");
-
-    if !menu_path.exists() {
-        return Err(MenuError::FileNotFound("configs/menu_config.yaml".to_string()));
-    }
-
-    let content = tokio_fs::read_to_string(menu_path).await?;
-    let commands = serde_yaml::from_str::<Vec<CommandInfo>>(&content)?;
-    Ok(commands)
-}
-
+// The project config, configs/menu_config_color.yaml, merged with its
+// includes and the other layers, see layers::config_layers
 pub async fn load_menu_config_color() -> Result<MenuConfig, MenuError> {
-    let config_path = Path::new("configs/menu_config_color.yaml");
-
-    if !config_path.exists() {
-        return Err(MenuError::FileNotFound("configs/menu_config_color.yaml".to_string()));
-    }
-
-    let content = tokio_fs::read_to_string(config_path).await?;
-    let config = serde_yaml::from_str::<MenuConfig>(&content)?;
-    println!("Successfully loaded menu config with {} items", config.menu_items.len());
+    let (config, _) = load_layered_config()?;
+    config.validate_state_machines().map_err(MenuError::InvalidConfig)?;
+    config.validate_requirements().map_err(MenuError::InvalidConfig)?;
+    config.validate_timers().map_err(MenuError::InvalidConfig)?;
+    eprintln!("Successfully loaded menu config with {} items", config.menu_items.len());
     Ok(config)
 }
 
-pub fn group_menu_commands(commands: &[CommandInfo]) -> HashMap<String, Vec<CommandInfo>> {
-    let mut grouped = HashMap::new();
-
-    for cmd in commands {
-        let entry = grouped.entry(cmd.category.clone()).or_insert_with(Vec::new);
-        entry.push(cmd.clone());
-    }
-
-    grouped
-}
-
-pub fn build_grouped_entries(commands: &[CommandInfo]) -> Vec<GroupedMenuEntry> {
-    let mut result = Vec::new();
-    let grouped = group_menu_commands(commands);
-
-    for (category, cmds) in grouped {
-        if !cmds.is_empty() {
-            let path_name = if let Some(first_cmd) = cmds.first() {
-                let parts: Vec<&str> = first_cmd.command.split(" ").collect();
-                if parts.len() >= 3 {
-                    parts[2].to_string()
-                } else {
-                    category.clone()
-                }
-            } else {
-                category.clone()
-            };
-
-            let actions = cmds.iter()
-                .map(|cmd| {
-                    let parts: Vec<&str> = cmd.name.split(' ').collect();
-                    if parts.len() > 1 {
-                        parts[parts.len() - 1].to_string()
-                    } else {
-                        "default".to_string()
-                    }
-                })
-                .collect();
-
-            let commands = cmds.iter()
-                .map(|cmd| cmd.command.clone())
-                .collect();
-
-            result.push(GroupedMenuEntry {
-                program: category.clone(),
-                path_name,
-                actions,
-                commands,
-            });
-        }
-    }
-
-    result
-}
-
-pub async fn load_menu_with_button_manager() -> Result<(Vec<CommandInfo>, ButtonManager), MenuError> {
+pub async fn load_menu_with_button_manager() -> Result<ButtonManager, MenuError> {
     // Load state machine data from menu_config_color.yaml
     let config = load_menu_config_color().await?;
     
-    // Extract command info from the MenuConfig
-    let mut commands = Vec::new();
-    
-    for item in &config.menu_items {
-        // Extract ALL available actions from ALL states' transitions
-        let mut actions = Vec::new();
-        
-        // Collect actions from all states, not just default state
-        for (_state_name, state) in &item.state_machine.states {
-            for action in state.transitions.keys() {
-                if !actions.contains(action) {
-                    actions.push(action.clone());
-                }
-            }
-        }
-        
-        println!("Found {} actions for menu item: {}", actions.len(), item.label);
-        
-        // Create CommandInfo objects from the actions
-        for action in &actions {
-            commands.push(CommandInfo {
-                name: format!("{} {}", item.label, action),
-                command: item.command_template.replace("{ACTION}", action),
-                description: format!("{} operation for {}", action, item.label),
-                category: item.label.clone(),
-            });
-        }
-    }
-    
-    println!("Extracted {} commands from menu_config_color.yaml", commands.len());
-    
     // Create button manager from the same config
-    let button_manager = ButtonManager::from_menu_config(config);
-    
-    Ok((commands, button_manager))
+    Ok(ButtonManager::from_menu_config(config))
 }
 
-pub fn extract_command_info(_line: &str) -> Option<CommandInfo> {
-    None // Not used with the new menu formats
+// UI entries of every menu item, in the order of the config
+pub fn menu_entries(config: &MenuConfig) -> Vec<SlintMenuEntry> {
+    config.menu_items.iter().map(SlintMenuEntry::from_item).collect()
 }
 
-pub fn create_slint_menu_entries(commands: &[CommandInfo]) -> Vec<SlintMenuEntry> {
-    let mut result = Vec::new();
-    let grouped = group_menu_commands(commands);
-    
-    for (category, cmds) in &grouped {
-        if !cmds.is_empty() {
-            let command_template = if let Some(first_cmd) = cmds.first() {
-                let parts: Vec<&str> = first_cmd.command.split(" ").collect();
-                if parts.len() >= 3 {
-                    format!("{} {{ACTION}} {}", parts[0], parts[2..].join(" "))
-                } else {
-                    first_cmd.command.clone()
-                }
-            } else {
-                String::new()
-            };
-            
-            let mut actions = Vec::new();
-            for cmd in cmds {
-                let parts: Vec<&str> = cmd.name.split(' ').collect();
-                if parts.len() > 1 {
-                    let action = parts[parts.len() - 1].to_string();
-                    if !actions.contains(&action) {
-                        actions.push(action);
-                    }
-                }
-            }
-            
-            result.push(SlintMenuEntry {
-                label: category.clone(),
-                actions,
-                command_template,
-            });
-        }
-    }
-    
-    result
-}
+// Add an item to the project config, or replace the one with its label,
+// leaving what other layers and includes define where it is
+pub async fn save_menu_item(item: &MenuItemConfig) -> Result<(), MenuError> {
+    let path = Path::new(PROJECT_CONFIG);
+    let mut config = if path.exists() { read_config_file(path)? } else { MenuConfig::default() };
+    config.merge(MenuConfig { menu_items: vec![item.clone()], ..MenuConfig::default() });
 
-#[deprecated(
-    since = "0.2.0",
-    note = "Use ButtonManager::from_menu_config() with data from menu_config_color.yaml instead"
-)]
-pub fn initialize_button_manager(profiles: &[CommandInfo]) -> ButtonManager {
-    let mut manager = ButtonManager::new();
-    
-    let grouped = group_menu_commands(profiles);
-    
-    for (profile_name, cmds) in grouped {
-        let mut actions = Vec::new();
-        for cmd in &cmds {
-            let parts: Vec<&str> = cmd.name.split(' ').collect();
-            if parts.len() > 1 {
-                let action = parts[parts.len() - 1];
-                if !actions.contains(&action) {
-                    actions.push(action);
-                }
-            }
-        }
-        
-        if actions.contains(&"freeze") && actions.contains(&"unfreeze") {
-            let freeze_key = ButtonManager::make_key(&profile_name, "freeze");
-            let unfreeze_key = ButtonManager::make_key(&profile_name, "unfreeze");
-            
-            manager.button_colors.insert(freeze_key, "#007BFF".to_string());
-            manager.button_colors.insert(unfreeze_key, "#007BFF".to_string());
-        }
-        
-        if actions.contains(&"kill") && actions.contains(&"start") {
-            let kill_key = ButtonManager::make_key(&profile_name, "kill");
-            let start_key = ButtonManager::make_key(&profile_name, "start");
-            
-            manager.button_colors.insert(kill_key, "#007BFF".to_string());
-            manager.button_colors.insert(start_key, "#007BFF".to_string());
-        }
-    }
-    
-    manager
+    let content = serde_yaml::to_string(&config)?;
+    tokio_fs::write(path, content).await?;
+    println!("Saved {} to {}", item.label, PROJECT_CONFIG);
+    Ok(())
 }
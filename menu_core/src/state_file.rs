@@ -0,0 +1,73 @@
+// menu_core/src/state_file.rs
+// The states the menu last had its items in, kept on disk so `graph` can
+// show them for items that are not running
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::parser_async::MenuError;
+
+// An item's main state and the state of each of its active regions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    pub state: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub regions: BTreeMap<String, String>,
+}
+
+// Saved states by item label
+pub type SavedStates = BTreeMap<String, SavedState>;
+
+// $XDG_STATE_HOME/menu_runner/states.yaml, or under ~/.local/state
+pub fn state_file_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(dir.join("menu_runner/states.yaml"))
+}
+
+// No file yet means no saved states
+pub fn load_states(path: &Path) -> Result<SavedStates, MenuError> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_yaml::from_str(&content).map_err(|e| MenuError::ParseError(format!("{}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SavedStates::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save_states(path: &Path, states: &SavedStates) -> Result<(), MenuError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_yaml::to_string(states)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_survive_a_round_trip() {
+        let dir = std::env::temp_dir().join(format!("menu-runner-states-{}", std::process::id()));
+        let path = dir.join("nested/states.yaml");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(load_states(&path).unwrap(), SavedStates::new());
+
+        let states = SavedStates::from([
+            ("vpn".to_string(), SavedState { state: "started".to_string(), regions: BTreeMap::new() }),
+            (
+                "work".to_string(),
+                SavedState { state: "running".to_string(), regions: BTreeMap::from([("audio".to_string(), "muted".to_string())]) },
+            ),
+        ]);
+        save_states(&path, &states).unwrap();
+        let loaded = load_states(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(loaded.unwrap(), states);
+    }
+}
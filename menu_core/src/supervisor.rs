@@ -0,0 +1,197 @@
+// menu_core/src/supervisor.rs
+// Bookkeeping for menu items whose process the menu keeps alive
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::models::{RestartPolicy, SupervisorConfig};
+
+// What to do after a supervised process exited
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExitDecision {
+    // Stopped on request, or exited in a way the policy accepts
+    Stopped,
+    // Failed and is not going to be restarted
+    Crashed,
+    // Start it again after `delay`
+    Restart { delay: Duration, attempt: u32 },
+}
+
+struct Supervised {
+    pid: Option<u32>,
+    started: Instant,
+    restarts: u32,
+    stop_requested: bool,
+}
+
+// Tracks the process of every supervised menu item by label. Spawning and
+// waiting is up to the caller, which reports each start and exit here.
+#[derive(Default)]
+pub struct Supervisor {
+    processes: HashMap<String, Supervised>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor::default()
+    }
+
+    // Whether the item has a process running or waiting to be restarted
+    pub fn is_supervising(&self, label: &str) -> bool {
+        self.processes.contains_key(label)
+    }
+
+    pub fn pid(&self, label: &str) -> Option<u32> {
+        self.processes.get(label)?.pid
+    }
+
+    // Record a (re)started process, keeping the restart count
+    pub fn started(&mut self, label: &str, pid: u32) {
+        let entry = self.processes.entry(label.to_string()).or_insert(Supervised {
+            pid: None,
+            started: Instant::now(),
+            restarts: 0,
+            stop_requested: false,
+        });
+        entry.pid = Some(pid);
+        entry.started = Instant::now();
+    }
+
+    // The user stopped the item: its next exit is not a crash and pending
+    // restarts are cancelled. Returns the pid to signal, if it is running.
+    pub fn request_stop(&mut self, label: &str) -> Option<u32> {
+        let entry = self.processes.get_mut(label)?;
+        entry.stop_requested = true;
+        entry.pid
+    }
+
+    pub fn exited(&mut self, label: &str, config: &SupervisorConfig, success: bool) -> ExitDecision {
+        let Some(entry) = self.processes.get_mut(label) else {
+            return ExitDecision::Stopped;
+        };
+        entry.pid = None;
+
+        let restart = match config.restart {
+            _ if entry.stop_requested => false,
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !success,
+            RestartPolicy::Always => true,
+        };
+        if !restart {
+            let stopped = success || entry.stop_requested;
+            self.processes.remove(label);
+            return if stopped { ExitDecision::Stopped } else { ExitDecision::Crashed };
+        }
+
+        // A process that stayed up for a full backoff period starts over
+        if entry.started.elapsed() >= Duration::from_secs(config.max_backoff_secs) {
+            entry.restarts = 0;
+        }
+        if config.max_restarts.is_some_and(|max| entry.restarts >= max) {
+            self.processes.remove(label);
+            return ExitDecision::Crashed;
+        }
+
+        let delay = config
+            .backoff_secs
+            .saturating_mul(1u64 << entry.restarts.min(20))
+            .min(config.max_backoff_secs);
+        entry.restarts += 1;
+
+        ExitDecision::Restart {
+            delay: Duration::from_secs(delay),
+            attempt: entry.restarts,
+        }
+    }
+
+    // Called when a restart delay is over; false (and the item is dropped)
+    // when the user stopped it in the meantime
+    pub fn should_restart(&mut self, label: &str) -> bool {
+        match self.processes.get(label) {
+            Some(entry) if !entry.stop_requested => true,
+            Some(_) => {
+                self.processes.remove(label);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(restart: RestartPolicy, max_restarts: Option<u32>) -> SupervisorConfig {
+        SupervisorConfig {
+            restart,
+            max_restarts,
+            backoff_secs: 2,
+            max_backoff_secs: 10,
+        }
+    }
+
+    fn restart_delay(decision: ExitDecision) -> u64 {
+        match decision {
+            ExitDecision::Restart { delay, .. } => delay.as_secs(),
+            other => panic!("expected a restart, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let config = config(RestartPolicy::Always, None);
+        let mut supervisor = Supervisor::new();
+
+        let mut delays = Vec::new();
+        for pid in 1..=5 {
+            supervisor.started("vpn", pid);
+            delays.push(restart_delay(supervisor.exited("vpn", &config, false)));
+        }
+        assert_eq!(delays, [2, 4, 8, 10, 10]);
+    }
+
+    #[test]
+    fn gives_up_after_max_restarts() {
+        let config = config(RestartPolicy::OnFailure, Some(2));
+        let mut supervisor = Supervisor::new();
+
+        for attempt in 1..=2 {
+            supervisor.started("vpn", 1);
+            assert_eq!(
+                supervisor.exited("vpn", &config, false),
+                ExitDecision::Restart { delay: Duration::from_secs(2 << (attempt - 1)), attempt },
+            );
+        }
+        supervisor.started("vpn", 1);
+        assert_eq!(supervisor.exited("vpn", &config, false), ExitDecision::Crashed);
+        assert!(!supervisor.is_supervising("vpn"));
+    }
+
+    #[test]
+    fn policy_decides_between_stopped_and_crashed() {
+        let mut supervisor = Supervisor::new();
+
+        supervisor.started("a", 1);
+        assert_eq!(supervisor.exited("a", &config(RestartPolicy::OnFailure, None), true), ExitDecision::Stopped);
+        supervisor.started("a", 1);
+        assert_eq!(supervisor.exited("a", &config(RestartPolicy::Never, None), false), ExitDecision::Crashed);
+        assert!(!supervisor.is_supervising("a"));
+    }
+
+    #[test]
+    fn requested_stops_are_not_restarted() {
+        let config = config(RestartPolicy::Always, None);
+        let mut supervisor = Supervisor::new();
+
+        supervisor.started("vpn", 42);
+        assert_eq!(supervisor.request_stop("vpn"), Some(42));
+        assert_eq!(supervisor.exited("vpn", &config, false), ExitDecision::Stopped);
+
+        // a stop during the restart delay cancels the restart
+        supervisor.started("vpn", 43);
+        restart_delay(supervisor.exited("vpn", &config, false));
+        assert_eq!(supervisor.request_stop("vpn"), None);
+        assert!(!supervisor.should_restart("vpn"));
+        assert!(!supervisor.is_supervising("vpn"));
+    }
+}
@@ -0,0 +1,550 @@
+// src/actions.rs
+// Running menu item actions off the UI thread, one item or many at once
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use Menu_Runner_core::{ActionCommand, ButtonManager, Hook, MenuItemConfig, Runner, Selection, SessionStep, Transition};
+use Menu_Runner_system::{run_action, status, ContainerStatus, Runtime as SystemRuntime, Target};
+
+// Items a bulk action works on at the same time when the config does not say
+pub const DEFAULT_PARALLELISM: usize = 4;
+
+// A guard probe still running after this counts as failed
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// An action to run for an item, with the transition pressing it took and
+// the state hooks to run once the action's command succeeded
+#[derive(Debug, Clone)]
+pub struct ItemCommand {
+    pub item: MenuItemConfig,
+    pub action: String,
+    pub transition: Transition,
+    pub hooks: Vec<Hook>,
+}
+
+impl ItemCommand {
+    // Run the action, then its hooks if it succeeded. Hooks of a failed
+    // action are dropped: the item never got to the state they belong to.
+    pub async fn run(&self) -> Result<String, String> {
+        let result = run_item(&self.item, &self.action, Some(&self.transition)).await;
+        match &result {
+            Ok(_) => run_hooks(self.hooks.clone()).await,
+            Err(_) => drop_hooks(&self.hooks),
+        }
+        result
+    }
+}
+
+// Run one action for a menu item: in-process for `runner: system` items,
+// through the shell otherwise or when the transition has its own command.
+// Returns what the command printed.
+pub async fn run_item(item: &MenuItemConfig, action: &str, transition: Option<&Transition>) -> Result<String, String> {
+    let own_command = transition.is_some_and(|transition| transition.command.is_some());
+    let run = async {
+        if item.runner == Runner::System && !own_command {
+            run_system(item, transition.map_or(action, |transition| transition.runs(action))).await
+        } else {
+            run_shell(&item.action_command(action, transition)).await
+        }
+    };
+
+    match transition.and_then(|transition| transition.timeout_secs) {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), run)
+            .await
+            .unwrap_or_else(|_| Err(format!("{} {} timed out after {}s", action, item.label, secs))),
+        None => run.await,
+    }
+}
+
+async fn run_system(item: &MenuItemConfig, action: &str) -> Result<String, String> {
+    let (app, profile) = item.target()
+        .ok_or_else(|| format!("Menu item {} has no app and profile", item.label))?;
+    let flags = item.option_flags();
+    let target = Target::new(&app, &profile).map_err(|e| e.to_string())?;
+    let runtime = SystemRuntime::from_flags(&flags)?;
+    run_action(&runtime, &target, action, &flags).await.map_err(|e| e.to_string())
+}
+
+// The shell is killed when the command is dropped, e.g. on a timeout
+async fn run_shell(command: &ActionCommand) -> Result<String, String> {
+    println!("Running command: {}", command.command);
+    let mut shell = tokio::process::Command::new("sh");
+    shell.arg("-c").arg(&command.command).envs(&command.env).kill_on_drop(true);
+    if let Some(dir) = &command.working_dir {
+        shell.current_dir(dir);
+    }
+
+    let output = shell
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(if stderr.is_empty() { format!("Command exited with {}", output.status) } else { stderr })
+    }
+}
+
+// Run guard probe commands at the same time, each on a tokio task
+pub async fn check_probes(probes: Vec<String>) -> Vec<(String, bool)> {
+    let mut tasks = JoinSet::new();
+    for probe in probes {
+        tasks.spawn(async move {
+            let success = run_probe(&probe).await;
+            (probe, success)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok(result) = joined {
+            results.push(result);
+        }
+    }
+    results
+}
+
+// Run every guard probe once and record the results, for callers like the
+// command line that check guards right away
+pub async fn refresh_probes(manager: &mut ButtonManager) {
+    for (probe, success) in check_probes(manager.guard_probes()).await {
+        manager.record_probe(&probe, success);
+    }
+}
+
+async fn run_probe(probe: &str) -> bool {
+    let mut shell = tokio::process::Command::new("sh");
+    shell.arg("-c")
+        .arg(probe)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    match tokio::time::timeout(PROBE_TIMEOUT, shell.status()).await {
+        Ok(Ok(status)) => status.success(),
+        _ => false,
+    }
+}
+
+// Run actions for many items, at most `parallelism` at a time. Results
+// come back in the order of `commands`.
+pub async fn run_bulk(commands: Vec<ItemCommand>, parallelism: usize) -> Vec<(String, Result<String, String>)> {
+    let semaphore = Arc::new(Semaphore::new(parallelism.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for (index, command) in commands.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let result = command.run().await;
+            (index, command.item.label, result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            Err(e) => println!("Bulk task failed: {}", e),
+        }
+    }
+
+    results.sort_by_key(|(index, _, _)| *index);
+    results.into_iter().map(|(_, label, result)| (label, result)).collect()
+}
+
+// Press `action` on every selected item that allows it, one after the
+// other as if each was clicked. `press` is given each item's step, normally
+// press_step, so prerequisites are started first and dependents brought
+// down; an item whose plan fails is skipped with the reason. Items an
+// earlier item's plan already took care of are left out.
+pub fn press_bulk<P>(manager: &mut ButtonManager, action: &str, selection: &Selection, mut press: P) -> Vec<(String, Pressed)>
+where
+    P: FnMut(&mut ButtonManager, &SessionStep) -> Pressed,
+{
+    let mut pressed: Vec<(String, Pressed)> = Vec::new();
+
+    for label in manager.bulk_targets(action, selection) {
+        let handled = pressed.iter().any(|(_, earlier)| match earlier {
+            Pressed::Run(commands) => commands.iter().any(|command| command.item.label == label),
+            _ => false,
+        });
+        if handled {
+            continue;
+        }
+
+        let step = SessionStep { label: label.clone(), action: action.to_string(), delay_secs: 0 };
+        let result = press(manager, &step);
+        pressed.push((label, result));
+    }
+
+    pressed
+}
+
+// Run what press_bulk pressed. Items that stand alone run at most
+// `parallelism` at a time. Those that brought in prerequisites or
+// dependents, or that require or are required by another item in the bulk,
+// run one after the other in the order they were pressed, which is the
+// order their plans expect. Results come back in that order too.
+pub async fn run_pressed(pressed: Vec<(String, Pressed)>, parallelism: usize) -> Vec<(String, Result<String, String>)> {
+    let items: Vec<&MenuItemConfig> = pressed.iter()
+        .flat_map(|(_, pressed)| match pressed {
+            Pressed::Run(commands) => commands.iter().map(|command| &command.item).collect(),
+            _ => Vec::new(),
+        })
+        .collect();
+    let linked: Vec<bool> = pressed.iter()
+        .map(|(_, pressed)| match pressed {
+            Pressed::Run(commands) if commands.len() == 1 => {
+                let item = &commands[0].item;
+                items.iter().any(|other| {
+                    other.label != item.label
+                        && (item.requires.iter().any(|requirement| requirement.label == other.label)
+                            || other.requires.iter().any(|requirement| requirement.label == item.label))
+                })
+            }
+            _ => true,
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    let mut in_order = Vec::new();
+    let mut alone = Vec::new();
+    for (index, ((label, pressed), linked)) in pressed.into_iter().zip(linked).enumerate() {
+        match pressed {
+            Pressed::Run(mut commands) if !linked => alone.extend(commands.pop().map(|command| (index, command))),
+            Pressed::Run(commands) => in_order.push((index, label, commands)),
+            Pressed::Done => results.push((index, label, Ok(String::new()))),
+            Pressed::Skipped(reason) => results.push((index, label, Ok(format!("skipped, {}", reason)))),
+        }
+    }
+
+    let (indices, commands): (Vec<usize>, Vec<ItemCommand>) = alone.into_iter().unzip();
+    let chain = async {
+        let mut chained = Vec::new();
+        for (index, label, commands) in in_order {
+            let result = run_in_order(&commands).await;
+            chained.push((index, label, result));
+        }
+        chained
+    };
+    let (chained, parallel) = tokio::join!(chain, run_bulk(commands, parallelism));
+
+    results.extend(chained);
+    results.extend(indices.into_iter().zip(parallel).map(|(index, (label, result))| (index, label, result)));
+    results.sort_by_key(|(index, _, _)| *index);
+    results.into_iter().map(|(_, label, result)| (label, result)).collect()
+}
+
+// What pressing a session step's button came to
+pub enum Pressed {
+    // Commands that still have to run, in order: the item's own action last,
+    // after any prerequisites that were started for it
+    Run(Vec<ItemCommand>),
+    // The caller took care of it, e.g. a supervised item
+    Done,
+    // The item's state does not allow the action, or it does not exist
+    Skipped(String),
+}
+
+// Run a session's steps one after the other, waiting out each step's delay.
+// `press` moves the item's state machine and says whether to run its command.
+pub async fn run_session<P>(steps: Vec<SessionStep>, mut press: P) -> Vec<(String, Result<String, String>)>
+where
+    P: FnMut(&SessionStep) -> Pressed,
+{
+    let mut results = Vec::new();
+
+    for step in steps {
+        if step.delay_secs > 0 {
+            tokio::time::sleep(Duration::from_secs(step.delay_secs)).await;
+        }
+
+        let result = match press(&step) {
+            Pressed::Run(commands) => run_in_order(&commands).await,
+            Pressed::Done => Ok(String::new()),
+            Pressed::Skipped(reason) => Ok(format!("skipped, {}", reason)),
+        };
+        results.push((step.label, result));
+    }
+
+    results
+}
+
+// Run commands one after the other, stopping at the first failure
+async fn run_in_order(commands: &[ItemCommand]) -> Result<String, String> {
+    let mut reports = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        match command.run().await {
+            Ok(report) if !report.is_empty() => reports.push(report),
+            Ok(_) => {}
+            Err(e) => {
+                // The commands after it never run, and neither do their hooks
+                for skipped in &commands[index + 1..] {
+                    drop_hooks(&skipped.hooks);
+                }
+                if commands.len() > 1 {
+                    return Err(format!("{} {}: {}", command.action, command.item.label, e));
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(reports.join("\n"))
+}
+
+// Press a step's action on the item if its current state allows it, along
+// with whatever its prerequisites need, and bring down items that required
+// it in the state it is leaving
+pub fn press_step(manager: &mut ButtonManager, step: &SessionStep) -> Pressed {
+    if manager.find_item(&step.label).is_none() {
+        return Pressed::Skipped(format!("no menu item named {}", step.label));
+    }
+    if !manager.get_available_actions(&step.label).contains(&step.action) {
+        let state = manager.current_state(&step.label).unwrap_or_default();
+        return Pressed::Skipped(format!("cannot {} while {}", step.action, state));
+    }
+
+    let plan = match manager.plan_action(&step.label, &step.action) {
+        Ok(plan) => plan,
+        Err(e) => return Pressed::Skipped(e),
+    };
+
+    let mut commands: Vec<ItemCommand> = plan.into_iter()
+        .filter_map(|planned| press(manager, planned))
+        .collect();
+
+    let mut stops: Vec<ItemCommand> = manager.dependents_to_stop(&step.label)
+        .into_iter()
+        .filter_map(|dependent| press(manager, dependent))
+        .collect();
+    stops.append(&mut commands);
+
+    Pressed::Run(stops)
+}
+
+// Press one planned step, keeping what running it needs
+fn press(manager: &mut ButtonManager, step: SessionStep) -> Option<ItemCommand> {
+    let transition = manager.press_button(&step.label, &step.action)?;
+    let hooks = manager.take_hooks();
+    let item = manager.find_item(&step.label)?.clone();
+    Some(ItemCommand { item, action: step.action, transition, hooks })
+}
+
+// Run state hooks one after the other, in the order the states changed. They
+// run once the action that changed the state is done, so an on_enter hook
+// sees the item already in its new state. A failing hook is reported and
+// does not stop the others.
+pub async fn run_hooks(hooks: Vec<Hook>) {
+    for hook in hooks {
+        println!("Running hook for {}: {}", hook.label, hook.command);
+        let status = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&hook.command)
+            .stdin(Stdio::null())
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => println!("Hook for {} exited with {}", hook.label, status),
+            Err(e) => println!("Failed to run hook for {}: {}", hook.label, e),
+        }
+    }
+}
+
+pub fn drop_hooks(hooks: &[Hook]) {
+    for hook in hooks {
+        println!("Not running hook for {} after its action failed: {}", hook.label, hook.command);
+    }
+}
+
+// One line summary of a bulk action or session, naming the items that failed
+pub fn summarize(action: &str, results: &[(String, Result<String, String>)]) -> String {
+    let failed: Vec<String> = results.iter()
+        .filter_map(|(label, result)| result.as_ref().err().map(|e| format!("{} ({})", label, e)))
+        .collect();
+
+    match (results.len(), failed.len()) {
+        (0, _) => format!("{}: nothing to do right now", action),
+        (total, 0) => format!("{}: {} items done", action, total),
+        (total, _) => format!("{}: {} of {} items failed: {}", action, failed.len(), total, failed.join(", ")),
+    }
+}
+
+// Put every item with a container into the state its container is in, for
+// callers like the command line that did not see the menu's earlier clicks.
+// Returns the labels of the items found running or paused.
+pub async fn sync_states(manager: &mut ButtonManager) -> Vec<String> {
+    let Some(config) = manager.menu_config.clone() else { return Vec::new() };
+    let mut found = Vec::new();
+
+    for item in &config.menu_items {
+        let Some((app, profile)) = item.target() else { continue };
+        let (Ok(target), Ok(runtime)) = (Target::new(&app, &profile), SystemRuntime::from_flags(&item.option_flags())) else {
+            continue;
+        };
+
+        let event = match status(&runtime, &target).await {
+            Ok(ContainerStatus::Running) => "start",
+            Ok(ContainerStatus::Paused) => "pause",
+            _ => continue,
+        };
+        manager.apply_item_event(&item.label, event);
+        found.push(item.label.clone());
+    }
+
+    // Catching up with the containers is not a state change of their own
+    manager.take_hooks();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // A file hooks append to, removed afterwards
+    struct HookLog(PathBuf);
+
+    impl HookLog {
+        fn new(test: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("menu-runner-hooks-{}-{}", std::process::id(), test));
+            let _ = fs::remove_file(&path);
+            HookLog(path)
+        }
+
+        fn hook(&self, label: &str) -> Hook {
+            Hook { label: label.to_string(), command: format!("echo {} >> {}", label, self.0.display()) }
+        }
+
+        fn lines(&self) -> Vec<String> {
+            fs::read_to_string(&self.0).unwrap_or_default().lines().map(str::to_string).collect()
+        }
+    }
+
+    impl Drop for HookLog {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn command(label: &str, shell: &str, hooks: Vec<Hook>) -> ItemCommand {
+        let item: MenuItemConfig = serde_yaml::from_str(&format!("label: {}\ncommand_template: {}", label, shell)).unwrap();
+        ItemCommand { item, action: "start".to_string(), transition: Transition::to("started"), hooks }
+    }
+
+    #[tokio::test]
+    async fn hooks_run_only_after_the_action_succeeded() {
+        let log = HookLog::new("run");
+
+        assert_eq!(command("a", "echo done", vec![log.hook("a")]).run().await, Ok("done".to_string()));
+        assert!(command("b", "exit 3", vec![log.hook("b")]).run().await.is_err());
+        assert_eq!(log.lines(), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn bulk_results_keep_the_order_of_the_commands() {
+        let log = HookLog::new("bulk");
+        let commands = vec![
+            command("slow", "sleep 0.3", vec![log.hook("slow")]),
+            command("failing", "exit 1", vec![log.hook("failing")]),
+            command("fast", "true", vec![log.hook("fast")]),
+        ];
+
+        let results = run_bulk(commands, 3).await;
+
+        let labels: Vec<&str> = results.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, ["slow", "failing", "fast"]);
+        assert!(results[0].1.is_ok() && results[1].1.is_err() && results[2].1.is_ok());
+        // hooks run as each command finishes, not in the order given
+        assert_eq!(log.lines(), ["fast", "slow"]);
+    }
+
+    fn required(auto_start: bool) -> ButtonManager {
+        let config = serde_yaml::from_str(&format!(
+            "menu_items:
+- label: vpn
+  command_template: echo {{ACTION}} vpn
+- label: mail
+  command_template: echo {{ACTION}} mail
+  groups: [work]
+  requires:
+    - label: vpn
+      auto_start: {}
+",
+            auto_start
+        ))
+        .unwrap();
+        ButtonManager::from_menu_config(config)
+    }
+
+    fn labels(pressed: &[(String, Pressed)]) -> Vec<String> {
+        pressed.iter()
+            .flat_map(|(label, pressed)| match pressed {
+                Pressed::Run(commands) => commands.iter().map(|command| format!("{} {}", command.action, command.item.label)).collect(),
+                Pressed::Skipped(reason) => vec![format!("{}: {}", label, reason)],
+                Pressed::Done => vec![format!("{}: done", label)],
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn bulk_start_refuses_items_whose_prerequisite_is_down() {
+        let mut manager = required(false);
+        let selection = Selection::Group("work".to_string());
+
+        let pressed = press_bulk(&mut manager, "start", &selection, press_step);
+
+        assert_eq!(labels(&pressed), ["mail: mail needs vpn to be started first"]);
+        assert_eq!(manager.current_state("mail").as_deref(), Some("default"));
+        let results = run_pressed(pressed, 2).await;
+        assert_eq!(results, [("mail".to_string(), Ok("skipped, mail needs vpn to be started first".to_string()))]);
+    }
+
+    #[tokio::test]
+    async fn bulk_start_brings_up_auto_started_prerequisites_first() {
+        let mut manager = required(true);
+
+        let pressed = press_bulk(&mut manager, "start", &Selection::Group("work".to_string()), press_step);
+        assert_eq!(labels(&pressed), ["start vpn", "start mail"]);
+        assert_eq!(manager.current_state("vpn").as_deref(), Some("started"));
+
+        let results = run_pressed(pressed, 2).await;
+        assert_eq!(results, [("mail".to_string(), Ok("start vpn\nstart mail".to_string()))]);
+    }
+
+    #[test]
+    fn bulk_kill_brings_down_dependents_once() {
+        let mut manager = required(false);
+        manager.press_button("vpn", "start");
+        manager.press_button("mail", "start");
+
+        let pressed = press_bulk(&mut manager, "force_kill", &Selection::All, press_step);
+
+        // mail goes down with vpn, and is not pressed a second time
+        assert_eq!(labels(&pressed), ["kill mail", "force_kill vpn"]);
+    }
+
+    #[tokio::test]
+    async fn commands_after_a_failure_run_neither_their_action_nor_hooks() {
+        let log = HookLog::new("in-order");
+        let commands = vec![
+            command("vpn", "true", vec![log.hook("vpn")]),
+            command("proxy", "exit 1", vec![log.hook("proxy")]),
+            command("browser", &format!("echo ran >> {}", log.0.display()), vec![log.hook("browser")]),
+        ];
+
+        let error = run_in_order(&commands).await.unwrap_err();
+
+        assert!(error.starts_with("start proxy:"), "{}", error);
+        assert_eq!(log.lines(), ["vpn"]);
+    }
+}
@@ -0,0 +1,316 @@
+// src/cli.rs
+// Command line subcommands of Menu_Runner_menu, run instead of opening the window
+use Menu_Runner_core::{config_layers, load_layered_config, load_menu_with_button_manager, load_states, migrate_config, render_graph, state_file_path};
+use Menu_Runner_core::{GraphFormat, SavedStates, Selection};
+
+use crate::actions::{press_bulk, press_step, refresh_probes, run_pressed, run_session, summarize, sync_states, Pressed, DEFAULT_PARALLELISM};
+
+// Run the subcommand named by the first argument. None means there is no
+// subcommand and the window should open.
+pub async fn run(args: &[String]) -> Option<i32> {
+    let command = args.get(1)?;
+    let rest = &args[2..];
+
+    match command.as_str() {
+        "bulk" => Some(bulk(rest).await),
+        "session" => Some(session(rest).await),
+        "graph" => Some(graph(rest).await),
+        "migrate" => Some(migrate(rest).await),
+        "config" => Some(config(rest)),
+        "help" | "--help" | "-h" => {
+            print_usage(&args[0]);
+            Some(0)
+        }
+        _ => None,
+    }
+}
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {}                 open the menu", program);
+    eprintln!("       {} bulk <action> [--group <group> | --items <label,...>] [--parallel <n>]", program);
+    eprintln!("       {} session list", program);
+    eprintln!("       {} session <name> [start|stop]", program);
+    eprintln!("       {} graph [--item <label>] [--format dot|mermaid]", program);
+    eprintln!("       {} migrate <old config> [<new config>]", program);
+    eprintln!("       {} config dump|layers", program);
+}
+
+fn flag_value<'a>(flags: &'a [String], name: &str) -> Option<&'a str> {
+    flags.iter()
+        .position(|flag| flag == name)
+        .and_then(|i| flags.get(i + 1))
+        .map(String::as_str)
+}
+
+fn print_results(results: &[(String, Result<String, String>)]) {
+    for (label, result) in results {
+        match result {
+            Ok(report) if report.is_empty() => println!("{}: ok", label),
+            Ok(report) => println!("{}: {}", label, report),
+            Err(e) => println!("{}: failed: {}", label, e),
+        }
+    }
+}
+
+// `bulk <action>`: run the action on every selected item whose current
+// container state allows it, starting prerequisites and bringing down
+// dependents like a click on each item would
+async fn bulk(args: &[String]) -> i32 {
+    let Some(action) = args.first().filter(|arg| !arg.starts_with("--")) else {
+        eprintln!("bulk needs an action, e.g. bulk freeze");
+        return 1;
+    };
+
+    let selection = match (flag_value(args, "--group"), flag_value(args, "--items")) {
+        (Some(group), _) => Selection::Group(group.to_string()),
+        (None, Some(items)) => Selection::Labels(items.split(',').map(|label| label.trim().to_string()).collect()),
+        (None, None) => Selection::All,
+    };
+
+    let mut manager = match load_menu_with_button_manager().await {
+        Ok(manager) => manager,
+        Err(e) => {
+            eprintln!("Failed to load the menu config: {}", e);
+            return 1;
+        }
+    };
+
+    let parallelism = match flag_value(args, "--parallel").map(str::parse::<usize>) {
+        Some(Ok(parallelism)) => parallelism,
+        Some(Err(_)) => {
+            eprintln!("--parallel needs a number");
+            return 1;
+        }
+        None => manager.menu_config.as_ref()
+            .and_then(|config| config.parallelism)
+            .unwrap_or(DEFAULT_PARALLELISM),
+    };
+
+    sync_states(&mut manager).await;
+    refresh_probes(&mut manager).await;
+
+    // Supervised processes belong to the menu that started them
+    let pressed = press_bulk(&mut manager, action, &selection, |manager, step| {
+        match manager.find_item(&step.label) {
+            Some(item) if item.supervise.is_some() => {
+                Pressed::Skipped("supervised items can only be controlled from the menu".to_string())
+            }
+            _ => press_step(manager, step),
+        }
+    });
+
+    let results = run_pressed(pressed, parallelism).await;
+    print_results(&results);
+
+    println!("{}", summarize(action, &results));
+    if results.iter().any(|(_, result)| result.is_err()) { 1 } else { 0 }
+}
+
+// `session list` shows every session's aggregate state, `session <name>
+// [start|stop]` runs a session's steps in order
+async fn session(args: &[String]) -> i32 {
+    let Some(name) = args.first() else {
+        eprintln!("session needs a session name or list");
+        return 1;
+    };
+
+    let mut manager = match load_menu_with_button_manager().await {
+        Ok(manager) => manager,
+        Err(e) => {
+            eprintln!("Failed to load the menu config: {}", e);
+            return 1;
+        }
+    };
+    sync_states(&mut manager).await;
+    refresh_probes(&mut manager).await;
+
+    let sessions = manager.menu_config.as_ref().map(|config| config.sessions.clone()).unwrap_or_default();
+    if name == "list" {
+        for name in sessions.keys() {
+            println!("{}: {}", name, manager.session_status(name).unwrap_or_default());
+        }
+        return 0;
+    }
+
+    let Some(session) = sessions.get(name) else {
+        eprintln!("No session named {}", name);
+        return 1;
+    };
+    let stop = match args.get(1).map(String::as_str) {
+        None | Some("start") => false,
+        Some("stop") => true,
+        Some(other) => {
+            eprintln!("Unknown session command: {} (expected start or stop)", other);
+            return 1;
+        }
+    };
+
+    let results = run_session(session.steps(stop), |step| {
+        match manager.find_item(&step.label) {
+            Some(item) if item.supervise.is_some() => {
+                Pressed::Skipped("supervised items can only be controlled from the menu".to_string())
+            }
+            _ => press_step(&mut manager, step),
+        }
+    })
+    .await;
+    print_results(&results);
+
+    println!("{}", summarize(name, &results));
+    println!("{}: {}", name, manager.session_status(name).unwrap_or_default());
+    if results.iter().any(|(_, result)| result.is_err()) { 1 } else { 0 }
+}
+
+// `graph`: print the state machines as a Graphviz or Mermaid diagram, with
+// the current state of every item highlighted: the one it is found running
+// in, or else the one the menu saved
+async fn graph(args: &[String]) -> i32 {
+    let format = match flag_value(args, "--format").unwrap_or("dot").parse::<GraphFormat>() {
+        Ok(format) => format,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let mut manager = match load_menu_with_button_manager().await {
+        Ok(manager) => manager,
+        Err(e) => {
+            eprintln!("Failed to load the menu config: {}", e);
+            return 1;
+        }
+    };
+    let Some(config) = manager.menu_config.clone() else {
+        eprintln!("The menu config has no items");
+        return 1;
+    };
+
+    // Items found running are highlighted in the state they are in, the
+    // others in the state the menu last saved for them
+    let running = sync_states(&mut manager).await;
+    let saved = match state_file_path().map(|path| load_states(&path)) {
+        Some(Ok(saved)) => saved,
+        Some(Err(e)) => {
+            eprintln!("Ignoring the saved item states: {}", e);
+            SavedStates::new()
+        }
+        None => SavedStates::new(),
+    };
+    let current = |label: &str, region: Option<&str>| {
+        if running.iter().any(|running| running == label) {
+            return match region {
+                Some(region) => manager.region_state(label, region),
+                None => manager.current_state(label),
+            };
+        }
+        let saved = saved.get(label)?;
+        match region {
+            Some(region) => saved.regions.get(region).cloned(),
+            None => Some(saved.state.clone()),
+        }
+    };
+
+    match render_graph(&config, format, flag_value(args, "--item"), &current) {
+        Ok(graph) => {
+            print!("{}", graph);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// `migrate <old config> [<new config>]`: rewrite a menu config in one of the
+// older formats with state machines, printing it when no output is given
+async fn migrate(args: &[String]) -> i32 {
+    let Some(input) = args.first() else {
+        eprintln!("migrate needs the config to read, e.g. migrate configs/sav/menu_config.json");
+        return 1;
+    };
+
+    let content = match tokio::fs::read_to_string(input).await {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", input, e);
+            return 1;
+        }
+    };
+    let migrated = migrate_config(&content)
+        .and_then(|config| serde_yaml::to_string(&config).map_err(|e| e.to_string()));
+    let yaml = match migrated {
+        Ok(yaml) => yaml,
+        Err(e) => {
+            eprintln!("Failed to migrate {}: {}", input, e);
+            return 1;
+        }
+    };
+
+    match args.get(1) {
+        None => {
+            print!("{}", yaml);
+            0
+        }
+        Some(output) => match tokio::fs::write(output, yaml).await {
+            Ok(()) => {
+                eprintln!("Wrote {}", output);
+                0
+            }
+            Err(e) => {
+                eprintln!("Failed to write {}: {}", output, e);
+                1
+            }
+        },
+    }
+}
+
+// `config dump` prints the config the menu runs with, after includes and
+// layers are merged; `config layers` lists where each layer is looked for
+fn config(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        Some("dump") => {
+            let (config, files) = match load_layered_config() {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    eprintln!("Failed to load the menu config: {}", e);
+                    return 1;
+                }
+            };
+            let yaml = match serde_yaml::to_string(&config) {
+                Ok(yaml) => yaml,
+                Err(e) => {
+                    eprintln!("Failed to write the merged config: {}", e);
+                    return 1;
+                }
+            };
+
+            println!("# Merged from:");
+            for file in &files {
+                println!("#   {}", file.display());
+            }
+            print!("{}", yaml);
+
+            // Still worth seeing when it does not load, to find out why
+            let checks = config.validate_state_machines()
+                .and_then(|()| config.validate_requirements())
+                .and_then(|()| config.validate_timers());
+            if let Err(e) = checks {
+                eprintln!("Invalid config: {}", e);
+                return 1;
+            }
+            0
+        }
+        Some("layers") => {
+            for layer in config_layers() {
+                let found = if layer.path.exists() { "" } else { " (not found)" };
+                println!("{}: {}{}", layer.name, layer.path.display(), found);
+            }
+            0
+        }
+        _ => {
+            eprintln!("config needs dump or layers");
+            1
+        }
+    }
+}
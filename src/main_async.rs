@@ -1,16 +1,35 @@
 // Import necessary Rust and external crates
 use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::Path;
 use std::process::Command;
 use std::sync::Mutex;
 use std::collections::HashMap;
 
+mod actions;
+mod cli;
+
 // Include the Slint modules defined in your .slint files
 slint::include_modules!();
-use slint::{ModelRc, VecModel, SharedString};
+use slint::{Model, ModelRc, VecModel, SharedString};
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::runtime::Runtime;
 
 // Import the core types from our menu_core library
-use Menu_Runner_core::create_slint_menu_entries;
+use Menu_Runner_core::{menu_entries, save_menu_item, save_states, state_file_path, SavedStates};
+use Menu_Runner_core::{ActionCommand, ButtonManager, Hook, MenuConfig, MenuItemConfig, Runner, SlintMenuEntry, Transition};
+use Menu_Runner_core::{ExitDecision, Selection, SessionStep, Supervisor};
+use actions::Pressed;
+use Menu_Runner_system::{run_action, Runtime as SystemRuntime, Target};
+
+// First entry of the "Copy from" box in the new profile dialog
+const EMPTY_TEMPLATE_LABEL: &str = "(empty profile)";
+
+// First entry of the bulk action group box
+const ALL_ITEMS_LABEL: &str = "All items";
+
+// How often guards that no event reports on are checked again
+const GUARD_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
 
 fn main() {
     // Create the runtime with all features enabled
@@ -18,42 +37,69 @@ fn main() {
 
     // Enter the runtime context
     rt.block_on(async {
+        // Subcommands like `bulk` run without opening the window
+        let args: Vec<String> = std::env::args().collect();
+        if let Some(code) = cli::run(&args).await {
+            std::process::exit(code);
+        }
+
         println!("Starting async menu loader...");
 
         // Load menu and button manager - properly handle the Result type
-        let (commands, button_manager) = match Menu_Runner_core::load_menu_with_button_manager().await {
-            Ok((cmds, manager)) => (cmds, manager),
+        let button_manager = match Menu_Runner_core::load_menu_with_button_manager().await {
+            Ok(manager) => manager,
             Err(e) => {
                 println!("Error loading menu with button manager: {}", e);
-                (Vec::new(), Menu_Runner_core::ButtonManager::new())
+                Menu_Runner_core::ButtonManager::new()
             }
         };
-        
+
+        // Create the Slint menu entries straight from the menu items
+        let slint_entries = button_manager.menu_config.as_ref().map(menu_entries).unwrap_or_default();
         let button_manager = Rc::new(Mutex::new(button_manager));
         
-        if commands.is_empty() {
+        if slint_entries.is_empty() {
            println!("No valid menu items found. Please check your configs/menu_config_color.yaml format.");
            return;
         }
 
-        println!("Successfully loaded {} menu items", commands.len());
-
-        // Create the Slint menu entries from the command info
-        let slint_entries = create_slint_menu_entries(&commands);
         println!("Created {} menu entries for the UI", slint_entries.len());
 
         // Keep track of all possible actions per profile
-        let mut all_actions_by_profile: HashMap<String, Vec<String>> = HashMap::new();
-        for entry in &slint_entries {
-            all_actions_by_profile.insert(entry.label.clone(), entry.actions.clone());
-        }
+        let all_actions_by_profile = actions_by_profile(&slint_entries);
+
+        // Entries are shared with the callbacks so new profiles can be added at runtime
+        let slint_entries = Rc::new(RefCell::new(slint_entries));
+        let all_actions_by_profile = Rc::new(RefCell::new(all_actions_by_profile));
 
         // Create the main window from your Slint UI definition
         let main_window = MainWindow::new().unwrap();
         
-        // Function to build menu model with only available actions
-        let build_menu_model = move |button_manager: &std::sync::MutexGuard<'_, Menu_Runner_core::ButtonManager>| {
-            let menu_entries: Vec<MenuEntry> = slint_entries.iter().map(|entry| {
+        // Function to build menu model with only available actions. It also
+        // refreshes the session status line, which follows the same states,
+        // and saves the states for `graph` when they changed.
+        let entries_for_model = slint_entries.clone();
+        let actions_for_model = all_actions_by_profile.clone();
+        let window_for_model = main_window.as_weak();
+        let state_file = state_file_path();
+        let saved_states = RefCell::new(SavedStates::new());
+        let build_menu_model = Rc::new(move |button_manager: &std::sync::MutexGuard<'_, ButtonManager>| {
+            if let Some(window) = window_for_model.upgrade() {
+                window.set_session_status(session_status_text(button_manager).into());
+            }
+
+            let states = button_manager.saved_states();
+            if *saved_states.borrow() != states {
+                if let Some(path) = &state_file {
+                    if let Err(e) = save_states(path, &states) {
+                        println!("Failed to save the item states: {}", e);
+                    }
+                }
+                *saved_states.borrow_mut() = states;
+            }
+
+            let all_actions_by_profile = actions_for_model.borrow();
+            let menu_entries: Vec<MenuEntry> = entries_for_model.borrow().iter().map(|entry| {
                 // Get only available actions for current state
                 let available_actions = button_manager.get_available_actions(&entry.label);
                 
@@ -70,22 +116,32 @@ fn main() {
                     .map(|a| a.clone().into())
                     .collect();
                 
+                // Guards that do not hold disable the button, with the reason
+                let blocked_vec: Vec<SharedString> = filtered_actions.iter()
+                    .map(|a| button_manager.blocked_reason(&entry.label, a).unwrap_or_default().into())
+                    .collect();
+                
                 // Create a VecModel from the actions and convert it to ModelRc
                 let actions_model = Rc::new(VecModel::from(actions_vec));
                 
                 MenuEntry {
                     label: entry.label.clone().into(),
                     actions: ModelRc::from(actions_model),
+                    blocked: ModelRc::from(Rc::new(VecModel::from(blocked_vec))),
+                    countdown: button_manager.countdown(&entry.label).unwrap_or_default().into(),
                     command_template: entry.command_template.clone().into(),
                 }
             }).collect();
             
             Rc::new(VecModel::from(menu_entries))
-        };
+        });
         
         // Initial menu model
         let menu_model = build_menu_model(&button_manager.lock().unwrap());
         main_window.set_menu_items(ModelRc::from(menu_model.clone()));
+        main_window.set_profile_templates(template_model(&slint_entries.borrow()));
+        main_window.set_groups(group_model(&button_manager.lock().unwrap()));
+        main_window.set_sessions(session_model(&button_manager.lock().unwrap()));
         
         // Set up button color provider callback - simplified to avoid unnecessary calculations
         main_window.on_get_button_color(move |_profile, _action| {
@@ -94,78 +150,959 @@ fn main() {
             "#007BFF".into()
         });
         
+        // Processes of items with `supervise`, kept alive by their restart policy
+        let supervisor = Rc::new(RefCell::new(Supervisor::new()));
+
         // Set up command handler for when action buttons are clicked
+        let supervisor_click = supervisor.clone();
         let button_manager_click = button_manager.clone();
+        let build_menu_model_click = build_menu_model.clone();
         let weak_window = main_window.as_weak();
-        main_window.on_run_command(move |command_template, action| {
-            // Extract profile from command template
-            let profile_name: String = {
-                // Create a variable for the string to extend its lifetime
-                let cmd_str = command_template.to_string();
-                let parts: Vec<&str> = cmd_str.split('/').collect();
-                
-                if let Some(last) = parts.last() {
-                    // Extract profile from filename pattern
-                    if let Some(filename) = last.split('.').nth(1) {
-                        filename.to_string()
-                    } else {
-                        // Extract label from path as fallback
-                        parts.iter()
-                            .rev()
-                            .skip(1)
-                            .next()
-                            .unwrap_or(&"unknown")
-                            .to_string()
+        main_window.on_run_command(move |label, command_template, action| {
+            let profile_name = label.to_string();
+             
+            println!("Executing action '{}' for profile '{}'", action, profile_name);
+            
+            // Prerequisites from `requires:` have to be up first; when some
+            // need starting, everything runs as a sequence of steps
+            let plan = button_manager_click.lock().unwrap().plan_action(&profile_name, &action);
+            let plan = match plan {
+                Ok(plan) => plan,
+                Err(e) => {
+                    println!("{}", e);
+                    if let Some(window) = weak_window.upgrade() {
+                        window.set_status_message(e.into());
                     }
-                } else {
-                    "unknown".to_string()
+                    return;
                 }
             };
-             
-            println!("Executing action '{}' for profile '{}'", action, profile_name);
+            if plan.len() > 1 {
+                run_steps(
+                    plan,
+                    profile_name,
+                    supervisor_click.clone(),
+                    button_manager_click.clone(),
+                    build_menu_model_click.clone(),
+                    weak_window.clone(),
+                );
+                return;
+            }
             
             // Update button visual state using state machine
-            {
+            let (transition, hooks) = {
                 let mut manager = button_manager_click.lock().unwrap();
-                manager.press_button(&profile_name, &action.to_string());
-            }
+                let transition = manager.press_button(&profile_name, &action.to_string());
+                (transition, manager.take_hooks())
+            };
                  
-            // Replace the action placeholder - FIXED: Replace {ACTION} instead of just ACTION
-            let mut command_str = command_template.to_string().replace("{ACTION}", &action.to_string());
-
-            // Remove any quotes that would be interpreted literally by the shell
-            command_str = command_str.replace("\"./target/debug/Menu_Runner_system\"", "./target/debug/Menu_Runner_system");
-            command_str = command_str.replace("\"firefox", "firefox");
-            command_str = command_str.trim_end_matches('"').to_string();
-
-            println!("Running command: {}", command_str);
-
-            // Execute the command
-            let output_result = Command::new("sh")
-                .arg("-c")
-                .arg(&command_str)
-                .output();
-
-            match output_result {
-                Ok(output) => {
-                    let status = output.status;
-                    println!("Command completed with status: {}", status);
-                },
-                Err(e) => {
-                    println!("Failed to execute command: {}", e);
-                }
+            // Items with `runner: system` call Menu_Runner_system in-process,
+            // supervised items have their process managed by the menu, and
+            // everything else runs in the background. The state hooks run
+            // once the command succeeded.
+            let item = button_manager_click.lock().unwrap().find_item(&profile_name).cloned();
+            if let Some(item) = item.clone().filter(|item| item.supervise.is_some()) {
+                let Some(transition) = transition else {
+                    println!("{} cannot {} in its current state", item.label, action);
+                    return;
+                };
+                run_supervised_action(
+                    actions::ItemCommand { item, action: action.to_string(), transition, hooks },
+                    supervisor_click.clone(),
+                    button_manager_click.clone(),
+                    build_menu_model_click.clone(),
+                    weak_window.clone(),
+                );
+            } else if let Some(item) = item {
+                run_item_action(item, action.to_string(), transition, hooks);
+            } else {
+                // The UI's copy of the template only stands in for an item
+                // missing from the config
+                run_shell_command(&command_template.replace("{ACTION}", &action));
             }
             
             // Rebuild the menu model with updated states
-            let new_menu_model = build_menu_model(&button_manager_click.lock().unwrap());
+            let new_menu_model = build_menu_model_click(&button_manager_click.lock().unwrap());
             
             // Update the UI with the new menu model on state change
             if let Some(window) = weak_window.upgrade() {
                 window.set_menu_items(ModelRc::from(new_menu_model));
             }
+
+            stop_dependents(
+                &profile_name,
+                &supervisor_click,
+                &button_manager_click,
+                &build_menu_model_click,
+                &weak_window,
+            );
         });
 
+        // Toolbar bulk actions: press the action on every item that allows it,
+        // with its prerequisites and dependents, then run the commands a few
+        // at a time
+        let button_manager_bulk = button_manager.clone();
+        let build_menu_model_bulk = build_menu_model.clone();
+        let supervisor_bulk = supervisor.clone();
+        let weak_window = main_window.as_weak();
+        main_window.on_bulk_action(move |action, group| {
+            let action = action.to_string();
+            let selection = match group.as_str() {
+                "" => Selection::All,
+                group => Selection::Group(group.to_string()),
+            };
+
+            let (pressed, parallelism) = {
+                let mut manager = button_manager_bulk.lock().unwrap();
+                let pressed = actions::press_bulk(&mut manager, &action, &selection, |manager, step| {
+                    press_or_supervise(
+                        manager,
+                        step,
+                        &supervisor_bulk,
+                        &button_manager_bulk,
+                        &build_menu_model_bulk,
+                        &weak_window,
+                    )
+                });
+                let parallelism = manager.menu_config.as_ref()
+                    .and_then(|config| config.parallelism)
+                    .unwrap_or(actions::DEFAULT_PARALLELISM);
+                (pressed, parallelism)
+            };
+            println!("Bulk {} on {} items", action, pressed.len());
+
+            let Some(window) = weak_window.upgrade() else { return };
+            window.set_menu_items(ModelRc::from(build_menu_model_bulk(&button_manager_bulk.lock().unwrap())));
+
+            let weak_window = window.as_weak();
+            let task = slint::spawn_local(async move {
+                let results = actions::run_pressed(pressed, parallelism).await;
+                let message = actions::summarize(&action, &results);
+                println!("{}", message);
+                if let Some(window) = weak_window.upgrade() {
+                    window.set_status_message(message.into());
+                }
+            });
+            if let Err(e) = task {
+                println!("Failed to schedule bulk action: {}", e);
+            }
+        });
+
+        // Sessions run their steps in order, pressing each item's button as
+        // its turn comes so the menu follows along
+        let button_manager_session = button_manager.clone();
+        let build_menu_model_session = build_menu_model.clone();
+        let supervisor_session = supervisor.clone();
+        let weak_window = main_window.as_weak();
+        main_window.on_run_session(move |name, command| {
+            let name = name.to_string();
+            let Some(session) = button_manager_session.lock().unwrap()
+                .menu_config
+                .as_ref()
+                .and_then(|config| config.sessions.get(&name).cloned())
+            else {
+                return;
+            };
+            println!("Running session {} ({})", name, command);
+
+            run_steps(
+                session.steps(command == "stop"),
+                name,
+                supervisor_session.clone(),
+                button_manager_session.clone(),
+                build_menu_model_session.clone(),
+                weak_window.clone(),
+            );
+        });
+
+        // Reload the config layers without restarting. Items keep their
+        // state, and their timers, where the new config still has them.
+        let button_manager_reload = button_manager.clone();
+        let build_menu_model_reload = build_menu_model.clone();
+        let entries_reload = slint_entries.clone();
+        let actions_reload = all_actions_by_profile.clone();
+        let weak_window = main_window.as_weak();
+        main_window.on_refresh(move || {
+            let button_manager = button_manager_reload.clone();
+            let build_menu_model = build_menu_model_reload.clone();
+            let slint_entries = entries_reload.clone();
+            let all_actions_by_profile = actions_reload.clone();
+            let weak_window = weak_window.clone();
+            let task = slint::spawn_local(async move {
+                let config = Menu_Runner_core::load_menu_config_color().await;
+                let Some(window) = weak_window.upgrade() else { return };
+                let config = match config {
+                    Ok(config) => config,
+                    Err(e) => {
+                        println!("Failed to reload config: {}", e);
+                        window.set_status_message(format!("Failed to reload config: {}", e).into());
+                        return;
+                    }
+                };
+
+                let entries = menu_entries(&config);
+                let mut manager = button_manager.lock().unwrap();
+                manager.reload_config(config);
+                *all_actions_by_profile.borrow_mut() = actions_by_profile(&entries);
+                window.set_profile_templates(template_model(&entries));
+                *slint_entries.borrow_mut() = entries;
+                window.set_groups(group_model(&manager));
+                window.set_sessions(session_model(&manager));
+                window.set_menu_items(ModelRc::from(build_menu_model(&manager)));
+                window.set_status_message("Reloaded config".into());
+            });
+            if let Err(e) = task {
+                println!("Failed to schedule config reload: {}", e);
+            }
+        });
+
+        // Set up the new profile dialog
+        let button_manager_create = button_manager.clone();
+        let build_menu_model_create = build_menu_model.clone();
+        let weak_window = main_window.as_weak();
+        main_window.on_create_profile(move |name, template| {
+            let Some(config) = button_manager_create.lock().unwrap().menu_config.clone() else { return };
+            let name = name.trim().to_string();
+            let template = template.to_string();
+
+            // Copying a profile takes a while, so it runs in the background
+            // and the menu picks the new item up when it is done
+            let button_manager = button_manager_create.clone();
+            let build_menu_model = build_menu_model_create.clone();
+            let slint_entries = slint_entries.clone();
+            let all_actions_by_profile = all_actions_by_profile.clone();
+            let weak_window = weak_window.clone();
+            let task = slint::spawn_local(async move {
+                let created = create_profile(&config, &name, &template).await;
+                let Some(window) = weak_window.upgrade() else { return };
+                let item = match created {
+                    Ok(item) => item,
+                    Err(e) => {
+                        println!("Failed to create profile: {}", e);
+                        window.set_status_message(e.into());
+                        return;
+                    }
+                };
+
+                let label = item.label.clone();
+                {
+                    let mut manager = button_manager.lock().unwrap();
+                    manager.add_menu_item(item.clone());
+
+                    // Rebuild the UI entries from the updated config
+                    if let Some(config) = &manager.menu_config {
+                        let entries = menu_entries(config);
+                        *all_actions_by_profile.borrow_mut() = actions_by_profile(&entries);
+                        window.set_profile_templates(template_model(&entries));
+                        *slint_entries.borrow_mut() = entries;
+                    }
+                    window.set_menu_items(ModelRc::from(build_menu_model(&manager)));
+                }
+                window.set_new_profile_open(false);
+
+                // Write the new item back to menu_config_color.yaml; items
+                // of other layers and includes stay where they are
+                let message = match save_menu_item(&item).await {
+                    Ok(()) => format!("Created profile {}", label),
+                    Err(e) => format!("Created profile {} but failed to save the config: {}", label, e),
+                };
+                println!("{}", message);
+                if let Some(window) = weak_window.upgrade() {
+                    window.set_status_message(message.into());
+                }
+            });
+            if let Err(e) = task {
+                println!("Failed to schedule profile creation: {}", e);
+            }
+        });
+
+        // Follow container events so state changes made outside the menu,
+        // like closing Firefox, show up without clicking anything
+        // Each backend has its own event stream, so there is one watcher per
+        // runner and backend the items use
+        let mut runners: Vec<(String, Vec<String>)> = Vec::new();
+        let mut system_backends: Vec<(Vec<String>, Vec<String>)> = Vec::new();
+        if let Some(config) = &button_manager.lock().unwrap().menu_config {
+            for item in &config.menu_items {
+                let flags = item.backend_flags();
+                if item.runner == Runner::System {
+                    match system_backends.iter_mut().find(|(backend, _)| *backend == flags) {
+                        Some((_, labels)) => labels.push(item.label.clone()),
+                        None => system_backends.push((flags, vec![item.label.clone()])),
+                    }
+                } else if let Some(runner) = item.runner_command() {
+                    if !runners.contains(&(runner.clone(), flags.clone())) {
+                        runners.push((runner, flags));
+                    }
+                }
+            }
+        }
+        for (flags, labels) in system_backends {
+            println!("Watching container events on {} for {}", backend_name(&flags), labels.join(", "));
+            let watcher = watch_system_events(flags, supervisor.clone(), button_manager.clone(), build_menu_model.clone(), main_window.as_weak());
+            if let Err(e) = slint::spawn_local(watcher) {
+                println!("Failed to start container event watcher: {}", e);
+            }
+        }
+        for (runner, flags) in runners {
+            let watcher = watch_container_events(runner, flags, supervisor.clone(), button_manager.clone(), build_menu_model.clone(), main_window.as_weak());
+            if let Err(e) = slint::spawn_local(watcher) {
+                println!("Failed to start container event watcher: {}", e);
+            }
+        }
+
+        // Files, the environment and probe commands send no events, so
+        // guards on them are checked again every few seconds
+        let guard_timer = slint::Timer::default();
+        if button_manager.lock().unwrap().menu_config.as_ref().is_some_and(|config| config.has_guards()) {
+            let probing = Rc::new(std::cell::Cell::new(false));
+            let button_manager_guards = button_manager.clone();
+            let build_menu_model_guards = build_menu_model.clone();
+            let weak_window = main_window.as_weak();
+            let refresh = move || {
+                refresh_guards(probing.clone(), button_manager_guards.clone(), build_menu_model_guards.clone(), weak_window.clone());
+            };
+            refresh();
+            guard_timer.start(slint::TimerMode::Repeated, GUARD_REFRESH_INTERVAL, refresh);
+        }
+
+        // `after:` timers: press what is due like a click and keep the
+        // countdowns current
+        let after_timer = slint::Timer::default();
+        if button_manager.lock().unwrap().menu_config.as_ref().is_some_and(|config| config.has_timers()) {
+            let button_manager_timers = button_manager.clone();
+            let build_menu_model_timers = build_menu_model.clone();
+            let supervisor_timers = supervisor.clone();
+            let weak_window = main_window.as_weak();
+            after_timer.start(slint::TimerMode::Repeated, std::time::Duration::from_secs(1), move || {
+                let due = button_manager_timers.lock().unwrap().take_due_timers();
+                for step in due {
+                    println!("Timer of {} is up, pressing {}", step.label, step.action);
+                    let title = format!("timed {} of {}", step.action, step.label);
+                    run_steps(
+                        vec![step],
+                        title,
+                        supervisor_timers.clone(),
+                        button_manager_timers.clone(),
+                        build_menu_model_timers.clone(),
+                        weak_window.clone(),
+                    );
+                }
+
+                if let Some(window) = weak_window.upgrade() {
+                    update_countdowns(&window.get_menu_items(), &button_manager_timers.lock().unwrap());
+                }
+            });
+        }
+
         println!("Starting UI...");
         main_window.run().unwrap();
     });
 }
+
+fn actions_by_profile(entries: &[SlintMenuEntry]) -> HashMap<String, Vec<String>> {
+    entries.iter()
+        .map(|entry| (entry.label.clone(), entry.actions.clone()))
+        .collect()
+}
+
+// Profiles offered as templates in the new profile dialog
+fn template_model(entries: &[SlintMenuEntry]) -> ModelRc<SharedString> {
+    let mut labels: Vec<String> = entries.iter().map(|entry| entry.label.clone()).collect();
+    labels.sort();
+
+    let mut model: Vec<SharedString> = vec![EMPTY_TEMPLATE_LABEL.into()];
+    model.extend(labels.into_iter().map(SharedString::from));
+    ModelRc::from(Rc::new(VecModel::from(model)))
+}
+
+// Groups offered for bulk actions
+fn group_model(manager: &ButtonManager) -> ModelRc<SharedString> {
+    let mut model: Vec<SharedString> = vec![ALL_ITEMS_LABEL.into()];
+    if let Some(config) = &manager.menu_config {
+        model.extend(config.groups().into_iter().map(SharedString::from));
+    }
+    ModelRc::from(Rc::new(VecModel::from(model)))
+}
+
+fn session_model(manager: &ButtonManager) -> ModelRc<SharedString> {
+    let names: Vec<SharedString> = manager.menu_config.as_ref()
+        .map(|config| config.sessions.keys().map(SharedString::from).collect())
+        .unwrap_or_default();
+    ModelRc::from(Rc::new(VecModel::from(names)))
+}
+
+// Change only the countdown of each menu entry, so the buttons stay as they are
+fn update_countdowns(model: &ModelRc<MenuEntry>, manager: &ButtonManager) {
+    for row in 0..model.row_count() {
+        let Some(mut entry) = model.row_data(row) else { continue };
+        let countdown: SharedString = manager.countdown(&entry.label).unwrap_or_default().into();
+        if entry.countdown != countdown {
+            entry.countdown = countdown;
+            model.set_row_data(row, entry);
+        }
+    }
+}
+
+// One line per session with the states of its items
+fn session_status_text(manager: &ButtonManager) -> String {
+    let Some(config) = &manager.menu_config else { return String::new() };
+    config.sessions.keys()
+        .filter_map(|name| manager.session_status(name).map(|status| format!("{}: {}", name, status)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Create the profile directory through Menu_Runner_system and build the menu
+// item for it. The new profile lives next to the template profile (or the
+// first profile in the menu) and inherits its command template and options.
+async fn create_profile(config: &MenuConfig, name: &str, template: &str) -> Result<MenuItemConfig, String> {
+    if name.is_empty() || name.contains('/') || name.contains(char::is_whitespace) {
+        return Err("Profile names must be non-empty and contain no '/' or spaces".to_string());
+    }
+
+    if config.menu_items.iter().any(|item| item.label == name) {
+        return Err(format!("A profile named {} already exists", name));
+    }
+
+    let reference = if template.is_empty() {
+        config.menu_items.iter().find(|item| item.target().is_some())
+    } else {
+        config.menu_items.iter().find(|item| item.label == template)
+    }
+    .ok_or_else(|| format!("No profile to base {} on", name))?;
+
+    let (app, reference_profile) = reference.target()
+        .ok_or_else(|| format!("Cannot find the profile path in {}", reference.command_template))?;
+
+    // Profiles are named <prefix>.<label>, e.g. 0.Github
+    let reference_path = Path::new(&reference_profile);
+    let file_name = reference_path.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let new_file_name = match file_name.strip_suffix(&reference.label) {
+        Some(prefix) if !prefix.is_empty() => format!("{}{}", prefix, name),
+        _ => name.to_string(),
+    };
+    let new_profile = reference_path.with_file_name(new_file_name).to_string_lossy().to_string();
+
+    if reference.runner == Runner::System {
+        let mut flags = Vec::new();
+        if !template.is_empty() {
+            flags = vec!["--from".to_string(), reference_profile.clone()];
+        }
+
+        // Through the backend the reference profile's item uses
+        let target = Target::new(&app, &new_profile).map_err(|e| e.to_string())?;
+        let runtime = SystemRuntime::from_flags(&reference.option_flags())?;
+        let report = run_action(&runtime, &target, "create", &flags).await.map_err(|e| e.to_string())?;
+        println!("{}", report);
+    } else {
+        let mut create_command = reference.command_template
+            .replace("{ACTION}", "create")
+            .replace(&reference_profile, &new_profile);
+        if !template.is_empty() {
+            create_command.push_str(&format!(" --from {}", reference_profile));
+        }
+
+        println!("Running command: {}", create_command);
+        let output = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&create_command)
+            .output()
+            .await
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+    }
+
+    // The new profile works like the one it is based on, snapshots included
+    let mut item = MenuItemConfig::new(name, &reference.command_template.replace(&reference_profile, &new_profile));
+    item.state_machine = reference.state_machine.clone();
+    if reference.runner == Runner::System {
+        item.runner = Runner::System;
+        item.app = Some(app);
+        item.profile = Some(new_profile);
+    }
+    if !template.is_empty() {
+        item.limits = reference.limits.clone();
+        item.network = reference.network.clone();
+        item.downloads = reference.downloads.clone();
+        item.downloads_per_profile = reference.downloads_per_profile;
+        item.clipboard = reference.clipboard.clone();
+    }
+
+    Ok(item)
+}
+
+// Run `<runner> events` and feed each "<event> <container>" line into the
+// button manager, rebuilding the menu when an item changes state
+async fn watch_container_events<F>(
+    runner: String,
+    flags: Vec<String>,
+    supervisor: Rc<RefCell<Supervisor>>,
+    button_manager: Rc<Mutex<ButtonManager>>,
+    build_menu_model: Rc<F>,
+    weak_window: slint::Weak<MainWindow>,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let command = std::iter::once(format!("{} events", runner)).chain(flags).collect::<Vec<_>>().join(" ");
+    println!("Watching container events: {}", command);
+
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            println!("Failed to watch container events: {}", e);
+            return;
+        }
+    };
+
+    let Some(stdout) = child.stdout.take() else { return };
+    let mut lines = BufReader::new(stdout).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Some((event, container)) = line.split_once(' ') else { continue };
+        apply_container_event(&supervisor, &button_manager, &build_menu_model, &weak_window, container.trim(), event);
+    }
+
+    println!("Container event watcher for {} stopped", command);
+}
+
+// Same as watch_container_events, for `runner: system` items, reading the
+// event stream through the Menu_Runner_system library
+async fn watch_system_events<F>(
+    flags: Vec<String>,
+    supervisor: Rc<RefCell<Supervisor>>,
+    button_manager: Rc<Mutex<ButtonManager>>,
+    build_menu_model: Rc<F>,
+    weak_window: slint::Weak<MainWindow>,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let events = match SystemRuntime::from_flags(&flags) {
+        Ok(runtime) => runtime.events().await,
+        Err(e) => Err(e),
+    };
+
+    let mut events = match events {
+        Ok(events) => events,
+        Err(e) => {
+            println!("Failed to watch container events: {}", e);
+            return;
+        }
+    };
+
+    while let Ok(Some(event)) = events.next_event().await {
+        apply_container_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &event.container, &event.action);
+    }
+
+    println!("Container event watcher on {} stopped", backend_name(&flags));
+}
+
+// The backend a watcher's flags select, for its log lines
+fn backend_name(flags: &[String]) -> String {
+    match flags.iter().position(|flag| flag == "--backend").and_then(|at| flags.get(at + 1)) {
+        Some(backend) => format!("the {} backend", backend),
+        None => "the default backend".to_string(),
+    }
+}
+
+fn apply_container_event<F>(
+    supervisor: &Rc<RefCell<Supervisor>>,
+    button_manager: &Rc<Mutex<ButtonManager>>,
+    build_menu_model: &Rc<F>,
+    weak_window: &slint::Weak<MainWindow>,
+    container: &str,
+    event: &str,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let label = {
+        let mut manager = button_manager.lock().unwrap();
+        let Some(label) = manager.apply_container_event(container, event) else { return };
+        spawn_hooks(manager.take_hooks());
+
+        if let Some(window) = weak_window.upgrade() {
+            window.set_menu_items(ModelRc::from(build_menu_model(&manager)));
+        }
+        label
+    };
+
+    stop_dependents(&label, supervisor, button_manager, build_menu_model, weak_window);
+}
+
+// Bring down the items that required `label` in the state it just left
+fn stop_dependents<F>(
+    label: &str,
+    supervisor: &Rc<RefCell<Supervisor>>,
+    button_manager: &Rc<Mutex<ButtonManager>>,
+    build_menu_model: &Rc<F>,
+    weak_window: &slint::Weak<MainWindow>,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let stops = button_manager.lock().unwrap().dependents_to_stop(label);
+    if stops.is_empty() {
+        return;
+    }
+
+    println!("{} went down, stopping {} dependent steps", label, stops.len());
+    run_steps(
+        stops,
+        format!("stop dependents of {}", label),
+        supervisor.clone(),
+        button_manager.clone(),
+        build_menu_model.clone(),
+        weak_window.clone(),
+    );
+}
+
+// Press and run steps one at a time: a session, an item with prerequisites
+// to start first, or dependents to stop
+fn run_steps<F>(
+    steps: Vec<SessionStep>,
+    title: String,
+    supervisor: Rc<RefCell<Supervisor>>,
+    button_manager: Rc<Mutex<ButtonManager>>,
+    build_menu_model: Rc<F>,
+    weak_window: slint::Weak<MainWindow>,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let task = slint::spawn_local(async move {
+        let results = actions::run_session(steps, |step| {
+            let mut manager = button_manager.lock().unwrap();
+            let pressed = press_or_supervise(&mut manager, step, &supervisor, &button_manager, &build_menu_model, &weak_window);
+
+            if let Some(window) = weak_window.upgrade() {
+                window.set_menu_items(ModelRc::from(build_menu_model(&manager)));
+            }
+            pressed
+        })
+        .await;
+
+        let message = actions::summarize(&title, &results);
+        println!("{}", message);
+        if let Some(window) = weak_window.upgrade() {
+            window.set_status_message(message.into());
+        }
+    });
+
+    if let Err(e) = task {
+        println!("Failed to schedule steps: {}", e);
+    }
+}
+
+// Press a step's button: supervised items are handed to the supervisor
+// right away, everything else goes through actions::press_step
+fn press_or_supervise<F>(
+    manager: &mut ButtonManager,
+    step: &SessionStep,
+    supervisor: &Rc<RefCell<Supervisor>>,
+    button_manager: &Rc<Mutex<ButtonManager>>,
+    build_menu_model: &Rc<F>,
+    weak_window: &slint::Weak<MainWindow>,
+) -> Pressed
+where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let supervised = manager.find_item(&step.label)
+        .filter(|item| item.supervise.is_some())
+        .cloned();
+    match supervised {
+        Some(item) if manager.get_available_actions(&step.label).contains(&step.action) => {
+            match manager.press_button(&step.label, &step.action) {
+                Some(transition) => {
+                    let hooks = manager.take_hooks();
+                    run_supervised_action(
+                        actions::ItemCommand { item, action: step.action.clone(), transition, hooks },
+                        supervisor.clone(),
+                        button_manager.clone(),
+                        build_menu_model.clone(),
+                        weak_window.clone(),
+                    );
+                    Pressed::Done
+                }
+                None => Pressed::Skipped(format!("cannot {} right now", step.action)),
+            }
+        }
+        _ => actions::press_step(manager, step),
+    }
+}
+
+// Expand the command template for an action and run it through the shell
+fn run_shell_command(command: &str) {
+    let mut command_str = command.to_string();
+
+    // Remove any quotes that would be interpreted literally by the shell
+    command_str = command_str.replace("\"./target/debug/Menu_Runner_system\"", "./target/debug/Menu_Runner_system");
+    command_str = command_str.replace("\"firefox", "firefox");
+    command_str = command_str.trim_end_matches('"').to_string();
+
+    println!("Running command: {}", command_str);
+
+    // Execute the command
+    let output_result = Command::new("sh")
+        .arg("-c")
+        .arg(&command_str)
+        .output();
+
+    match output_result {
+        Ok(output) => {
+            let status = output.status;
+            println!("Command completed with status: {}", status);
+        },
+        Err(e) => {
+            println!("Failed to execute command: {}", e);
+        }
+    }
+}
+
+// Run the guard probes on tokio tasks, then redraw with their results. A
+// run still going when the next one is due is left to finish.
+fn refresh_guards<F>(
+    probing: Rc<std::cell::Cell<bool>>,
+    button_manager: Rc<Mutex<ButtonManager>>,
+    build_menu_model: Rc<F>,
+    weak_window: slint::Weak<MainWindow>,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    if probing.replace(true) {
+        return;
+    }
+
+    let probes = button_manager.lock().unwrap().guard_probes();
+    let task = slint::spawn_local(async move {
+        let results = actions::check_probes(probes).await;
+        let mut manager = button_manager.lock().unwrap();
+        for (probe, success) in results {
+            manager.record_probe(&probe, success);
+        }
+        if let Some(window) = weak_window.upgrade() {
+            window.set_menu_items(ModelRc::from(build_menu_model(&manager)));
+        }
+        probing.set(false);
+    });
+
+    if let Err(e) = task {
+        println!("Failed to schedule guard probes: {}", e);
+    }
+}
+
+// Run an action in the background: through the Menu_Runner_system library
+// without spawning a process, or a transition's own command
+fn run_item_action(item: MenuItemConfig, action: String, transition: Option<Transition>, hooks: Vec<Hook>) {
+    let label = item.label.clone();
+    let task = slint::spawn_local(async move {
+        match actions::run_item(&item, &action, transition.as_ref()).await {
+            Ok(report) => {
+                println!("{}", report);
+                actions::run_hooks(hooks).await;
+            }
+            Err(e) => {
+                println!("Failed to {} {}: {}", action, item.label, e);
+                actions::drop_hooks(&hooks);
+            }
+        }
+    });
+
+    if let Err(e) = task {
+        println!("Failed to schedule {}: {}", label, e);
+    }
+}
+
+// Hooks of a state change that already happened, e.g. a container event
+fn spawn_hooks(hooks: Vec<Hook>) {
+    if hooks.is_empty() {
+        return;
+    }
+    if let Err(e) = slint::spawn_local(actions::run_hooks(hooks)) {
+        println!("Failed to schedule hooks: {}", e);
+    }
+}
+
+// Start, stop or signal the process of a supervised menu item. Actions
+// without a process equivalent run their command as usual.
+fn run_supervised_action<F>(
+    command: actions::ItemCommand,
+    supervisor: Rc<RefCell<Supervisor>>,
+    button_manager: Rc<Mutex<ButtonManager>>,
+    build_menu_model: Rc<F>,
+    weak_window: slint::Weak<MainWindow>,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let actions::ItemCommand { item, action, transition, hooks } = command;
+    let signal = match transition.runs(&action) {
+        "start" => {
+            if supervisor.borrow().is_supervising(&item.label) {
+                println!("{} is already running", item.label);
+                actions::drop_hooks(&hooks);
+                return;
+            }
+
+            let label = item.label.clone();
+            let command = item.action_command(&action, Some(&transition));
+            let task = slint::spawn_local(supervise(item, command, hooks, supervisor, button_manager, build_menu_model, weak_window));
+            if let Err(e) = task {
+                println!("Failed to schedule {}: {}", label, e);
+            }
+            return;
+        }
+        "stop" => "TERM",
+        "kill" | "force_kill" => "KILL",
+        "freeze" => "STOP",
+        "unfreeze" => "CONT",
+        _ => {
+            run_item_action(item, action, Some(transition), hooks);
+            return;
+        }
+    };
+
+    // Stopping also cancels a restart that is waiting out its backoff
+    let pid = if signal == "TERM" || signal == "KILL" {
+        supervisor.borrow_mut().request_stop(&item.label)
+    } else {
+        supervisor.borrow().pid(&item.label)
+    };
+
+    let Some(pid) = pid else {
+        println!("{} has no running process to {}", item.label, action);
+        actions::drop_hooks(&hooks);
+        return;
+    };
+
+    // Signal the whole process group so children of the shell get it too
+    let result = Command::new("kill")
+        .arg(format!("-{}", signal))
+        .arg("--")
+        .arg(format!("-{}", pid))
+        .status();
+    match result {
+        Ok(status) if status.success() => spawn_hooks(hooks),
+        Ok(status) => {
+            println!("Failed to {} {}: kill exited with {}", action, item.label, status);
+            actions::drop_hooks(&hooks);
+        }
+        Err(e) => {
+            println!("Failed to {} {}: {}", action, item.label, e);
+            actions::drop_hooks(&hooks);
+        }
+    }
+}
+
+// Run a supervised item's start command until it stops for good, reporting
+// start/crash/restart/die events to the button manager along the way
+async fn supervise<F>(
+    item: MenuItemConfig,
+    command: ActionCommand,
+    mut hooks: Vec<Hook>,
+    supervisor: Rc<RefCell<Supervisor>>,
+    button_manager: Rc<Mutex<ButtonManager>>,
+    build_menu_model: Rc<F>,
+    weak_window: slint::Weak<MainWindow>,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    let Some(config) = item.supervise.clone() else { return };
+
+    // The command's timeout does not apply, the process is meant to keep running
+    loop {
+        println!("Running supervised command: {}", command.command);
+        let mut shell = tokio::process::Command::new("sh");
+        shell.arg("-c").arg(&command.command).envs(&command.env).process_group(0);
+        if let Some(dir) = &command.working_dir {
+            shell.current_dir(dir);
+        }
+        let child = shell.spawn();
+
+        let (mut child, pid) = match child.map(|child| (child.id(), child)) {
+            Ok((Some(pid), child)) => (child, pid),
+            Ok((None, _)) => {
+                println!("{} exited before it could be supervised", item.label);
+                actions::drop_hooks(&std::mem::take(&mut hooks));
+                apply_item_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &item.label, "crash");
+                return;
+            }
+            Err(e) => {
+                println!("Failed to start {}: {}", item.label, e);
+                actions::drop_hooks(&std::mem::take(&mut hooks));
+                apply_item_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &item.label, "crash");
+                return;
+            }
+        };
+
+        supervisor.borrow_mut().started(&item.label, pid);
+        apply_item_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &item.label, "start");
+        // The start button's hooks, once the process is up; restarts have none
+        spawn_hooks(std::mem::take(&mut hooks));
+
+        let success = child.wait().await.map(|status| status.success()).unwrap_or(false);
+        let decision = supervisor.borrow_mut().exited(&item.label, &config, success);
+        println!("Supervised process of {} exited: {:?}", item.label, decision);
+
+        match decision {
+            ExitDecision::Stopped => {
+                apply_item_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &item.label, "die");
+                return;
+            }
+            ExitDecision::Crashed => {
+                apply_item_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &item.label, "crash");
+                return;
+            }
+            ExitDecision::Restart { delay, attempt } => {
+                println!("Restarting {} in {:?} (attempt {})", item.label, delay, attempt);
+                apply_item_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &item.label, "restart");
+                tokio::time::sleep(delay).await;
+
+                if !supervisor.borrow_mut().should_restart(&item.label) {
+                    apply_item_event(&supervisor, &button_manager, &build_menu_model, &weak_window, &item.label, "die");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// Items whose state machine has no crashed or restarting state show a crash
+// as killed and a pending restart as still started
+fn apply_item_event<F>(
+    supervisor: &Rc<RefCell<Supervisor>>,
+    button_manager: &Rc<Mutex<ButtonManager>>,
+    build_menu_model: &Rc<F>,
+    weak_window: &slint::Weak<MainWindow>,
+    label: &str,
+    event: &str,
+) where
+    F: Fn(&std::sync::MutexGuard<'_, ButtonManager>) -> Rc<VecModel<MenuEntry>> + 'static,
+{
+    {
+        let mut manager = button_manager.lock().unwrap();
+        let event = match manager.find_item(label) {
+            Some(item) if item.state_for_event(event).is_some() => event,
+            Some(_) if event == "crash" => "die",
+            Some(_) if event == "restart" => "start",
+            _ => return,
+        };
+
+        if !manager.apply_item_event(label, event) {
+            return;
+        }
+        spawn_hooks(manager.take_hooks());
+        if let Some(window) = weak_window.upgrade() {
+            window.set_menu_items(ModelRc::from(build_menu_model(&manager)));
+        }
+    }
+
+    stop_dependents(label, supervisor, button_manager, build_menu_model, weak_window);
+}